@@ -0,0 +1,268 @@
+use super::cfg::CFG;
+use super::v2::dominance_tree::DominanceTree;
+use super::v2::graph::{Graph, Inverse};
+use super::{bb, bril, util};
+use std::collections::HashMap;
+
+/// The CFG extended with a virtual exit node. Every returning block flows into
+/// the exit, as does every block that can never reach a return (e.g. infinite
+/// loops), so that post-dominance is defined for every block.
+struct ExitGraph<'a> {
+    cfg: CFG<'a>,
+    exit_pred: Vec<usize>,
+    to_exit: Vec<bool>,
+}
+
+impl<'a> ExitGraph<'a> {
+    fn new(cfg: CFG<'a>) -> Self {
+        let mut to_exit: Vec<bool> = (0..cfg.len())
+            .map(|idx| cfg.get_block(idx).instrs().last().is_some_and(|i| i.is_return()))
+            .collect();
+        // Walk backwards from the returns, anything not found is stuck in a loop
+        let mut reaches_exit = to_exit.clone();
+        let mut stack: Vec<_> = (0..cfg.len()).filter(|&idx| to_exit[idx]).collect();
+        while let Some(idx) = stack.pop() {
+            for pred in cfg.predecessors(idx) {
+                if !reaches_exit[pred] {
+                    reaches_exit[pred] = true;
+                    stack.push(pred);
+                }
+            }
+        }
+        for (idx, reaches_exit) in reaches_exit.into_iter().enumerate() {
+            to_exit[idx] |= !reaches_exit;
+        }
+        let exit_pred = (0..cfg.len()).filter(|&idx| to_exit[idx]).collect();
+        ExitGraph {
+            cfg,
+            exit_pred,
+            to_exit,
+        }
+    }
+
+    fn exit(&self) -> usize {
+        self.cfg.len()
+    }
+}
+
+impl<'a> Graph for ExitGraph<'a> {
+    type Node = usize;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        self.cfg.entry_node()
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        Some(self.exit())
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        0..=self.exit()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        let pred: Vec<_> = if node == self.exit() {
+            self.exit_pred.clone()
+        } else {
+            self.cfg.predecessors(node).collect()
+        };
+        pred.into_iter()
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        let mut succ = Vec::new();
+        if node != self.exit() {
+            succ.extend(self.cfg.successors(node));
+            if self.to_exit[node] {
+                succ.push(self.exit());
+            }
+        }
+        succ.into_iter()
+    }
+}
+
+fn is_critical(instr: &bril::Instruction) -> bool {
+    match instr {
+        bril::Instruction::Constant { .. } => false,
+        bril::Instruction::Value { op, .. } => *op == bril::ValueOps::Call,
+        bril::Instruction::Effect { op, .. } => !matches!(
+            op,
+            bril::EffectOps::Jump | bril::EffectOps::Branch | bril::EffectOps::Nop
+        ),
+    }
+}
+
+fn is_branch(instr: &bril::Instruction) -> bool {
+    matches!(
+        instr,
+        bril::Instruction::Effect {
+            op: bril::EffectOps::Branch,
+            ..
+        }
+    )
+}
+
+struct Liveness<'a> {
+    blocks: &'a bb::BasicBlocks,
+    /// For each block, the blocks whose branch it is control dependent on.
+    control_deps: Vec<Vec<usize>>,
+    defs: HashMap<&'a String, Vec<(usize, usize)>>,
+    live: Vec<Vec<bool>>,
+    live_blocks: Vec<bool>,
+    worklist: Vec<(usize, usize)>,
+}
+
+impl<'a> Liveness<'a> {
+    fn new(blocks: &'a bb::BasicBlocks, control_deps: Vec<Vec<usize>>) -> Self {
+        let mut defs = HashMap::new();
+        for (block, bb) in blocks.blocks.iter().enumerate() {
+            for (idx, instr) in bb.instrs.iter().enumerate() {
+                if let Some(dest) = util::get_dest(instr) {
+                    defs.entry(dest).or_insert_with(Vec::new).push((block, idx));
+                }
+            }
+        }
+        Liveness {
+            blocks,
+            control_deps,
+            defs,
+            live: blocks
+                .blocks
+                .iter()
+                .map(|block| vec![false; block.instrs.len()])
+                .collect(),
+            live_blocks: vec![false; blocks.blocks.len()],
+            worklist: Vec::new(),
+        }
+    }
+
+    fn mark(&mut self, block: usize, idx: usize) {
+        if !self.live[block][idx] {
+            self.live[block][idx] = true;
+            self.worklist.push((block, idx));
+        }
+    }
+
+    fn mark_terminator(&mut self, block: usize) {
+        let idx = self.blocks.blocks[block].instrs.len() - 1;
+        self.mark(block, idx);
+    }
+
+    fn compute(&mut self) {
+        for (block, bb) in self.blocks.blocks.iter().enumerate() {
+            for (idx, instr) in bb.instrs.iter().enumerate() {
+                if is_critical(instr) {
+                    self.mark(block, idx);
+                }
+            }
+        }
+        while let Some((block, idx)) = self.worklist.pop() {
+            let instr = &self.blocks.blocks[block].instrs[idx];
+            // Every definition of a used variable may reach the use
+            for arg in instr.args() {
+                let defs = self.defs.get(arg).cloned().unwrap_or_default();
+                for (def_block, def_idx) in defs {
+                    self.mark(def_block, def_idx);
+                }
+            }
+            // A phi needs the edge it came in on to be preserved
+            if util::is_value_op(instr, bril::ValueOps::Phi) {
+                for label in util::get_labels(instr).unwrap() {
+                    if let Some(&pred) = self.blocks.labels.get(label) {
+                        self.mark_terminator(pred);
+                    }
+                }
+            }
+            // The block is only reached if the branches it depends on are taken
+            if !self.live_blocks[block] {
+                self.live_blocks[block] = true;
+                for dep in self.control_deps[block].clone() {
+                    self.mark_terminator(dep);
+                }
+            }
+        }
+    }
+}
+
+fn function_adce(blocks: &mut bb::BasicBlocks) {
+    if blocks.blocks.is_empty() {
+        return;
+    }
+    let cfg = CFG::new(blocks);
+    let graph = ExitGraph::new(cfg);
+    let exit = graph.exit();
+    let inverse = Inverse(&graph);
+    let post_dom = DominanceTree::new(&inverse);
+
+    // Block b is control dependent on x iff x is in the post-dominance frontier of b
+    let control_deps = (0..blocks.blocks.len())
+        .map(|block| {
+            post_dom
+                .dominance_frontier(block)
+                .filter(|&dep| dep != exit)
+                .filter(|&dep| is_branch(blocks.blocks[dep].instrs.last().unwrap()))
+                .collect()
+        })
+        .collect();
+
+    let mut liveness = Liveness::new(blocks, control_deps);
+    let targets = loop {
+        liveness.compute();
+
+        // Dead branches jump straight to the nearest post-dominator that does something
+        let mut targets = Vec::new();
+        let mut kept = Vec::new();
+        for (block, bb) in blocks.blocks.iter().enumerate() {
+            let terminator = bb.instrs.last().unwrap();
+            let mut target = None;
+            if is_branch(terminator) && !liveness.live[block][bb.instrs.len() - 1] {
+                let mut post_dominator = post_dom.immediate_dominator(block);
+                while let Some(node) = post_dominator {
+                    if node == exit || liveness.live_blocks[node] {
+                        break;
+                    }
+                    post_dominator = post_dom.immediate_dominator(node);
+                }
+                target = post_dominator.filter(|&node| node != exit);
+                if target.is_none() {
+                    kept.push(block);
+                }
+            }
+            targets.push(target);
+        }
+        if kept.is_empty() {
+            break targets;
+        }
+        // With nothing useful after it the branch stays, so its condition is needed
+        for block in kept {
+            liveness.mark_terminator(block);
+        }
+    };
+    let live = liveness.live;
+
+    for (block, target) in targets.into_iter().enumerate() {
+        let label = target.map(|target| blocks.blocks[target].label.clone());
+        let bb = &mut blocks.blocks[block];
+        let len = bb.instrs.len();
+        let instrs = std::mem::take(&mut bb.instrs);
+        bb.instrs = instrs
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| live[block][*idx] || *idx + 1 == len)
+            .map(|(_, instr)| instr)
+            .collect();
+        if let Some(label) = label {
+            *bb.instrs.last_mut().unwrap() = bril::Instruction::jump(label);
+        }
+    }
+}
+
+pub fn adce(program: &bril::Program) -> bril::Program {
+    let mut adce_program = program.clone();
+    for func in &mut adce_program.functions {
+        let mut blocks = bb::BasicBlocks::from(&func.instrs);
+        function_adce(&mut blocks);
+        func.instrs = blocks.to_instrs();
+    }
+    adce_program
+}
@@ -167,6 +167,46 @@ pub fn uninitialized_variables(
     data_flow(algo, blocks)
 }
 
+struct LiveVariablesAlgorithm {}
+
+impl DataFlowAlgorithm for LiveVariablesAlgorithm {
+    type Result = HashSet<String>;
+
+    fn direction(&self) -> DataFlowDirection {
+        DataFlowDirection::Backward
+    }
+
+    fn init(&self) -> Self::Result {
+        HashSet::new()
+    }
+
+    fn transfer(
+        &self,
+        _block_id: usize,
+        block: &bb::BasicBlock,
+        input: &Self::Result,
+    ) -> Self::Result {
+        let mut live = input.clone();
+        for instr in block.instrs.iter().rev() {
+            if let Some(dest) = util::get_dest(instr) {
+                live.remove(dest);
+            }
+            live.extend(instr.args().iter().cloned());
+        }
+        live
+    }
+
+    fn merge<'a>(&self, input: impl Iterator<Item = &'a Self::Result>) -> Self::Result {
+        input.fold(self.init(), |merged, input| &merged | input)
+    }
+}
+
+// Returns the variables live out of and live into each block
+pub fn live_variables(blocks: &bb::BasicBlocks) -> (Vec<HashSet<String>>, Vec<HashSet<String>>) {
+    let algo = LiveVariablesAlgorithm {};
+    data_flow(algo, blocks)
+}
+
 struct ConstantPropagationAlgorithm {
     // function: &'a bril::Function,
 }
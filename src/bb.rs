@@ -115,6 +115,14 @@ pub fn is_jump(instr: &bril::Instruction) -> bool {
     get_labels(instr).is_some()
 }
 
+fn rename_label(instr: &mut bril::Instruction, old: &str, new: &str) {
+    if let Some(labels) = util::get_labels_mut(instr) {
+        for label in labels.iter_mut().filter(|label| *label == old) {
+            *label = new.to_string();
+        }
+    }
+}
+
 impl BasicBlocks {
     pub fn get_referenced_variables(&self) -> impl Iterator<Item=&String> {
         let dest_iter = self
@@ -229,6 +237,44 @@ impl BasicBlocks {
         blocks
     }
 
+    /**
+     * Inserts a block that jumps to `header` and retargets the edges from
+     * `preds` to it, returning the new block's index. Phis in the header
+     * are retargeted as well, so with more than one predecessor the header
+     * must not have any.
+     */
+    pub fn insert_preheader(&mut self, header: usize, preds: &[usize]) -> usize {
+        let header_label = self.blocks[header].label.clone();
+        let label = self.create_label();
+        for &pred in preds {
+            let pred_label = self.blocks[pred].label.clone();
+            if let Some(terminator) = self.blocks[pred].instrs.last_mut() {
+                rename_label(terminator, &header_label, &label);
+            }
+            for instr in &mut self.blocks[header].instrs {
+                if util::is_value_op(instr, bril::ValueOps::Phi) {
+                    assert_eq!(preds.len(), 1, "can't merge phi arguments into a preheader");
+                    rename_label(instr, &pred_label, &label);
+                }
+            }
+        }
+        let mut preheader = BasicBlock::from(label);
+        preheader.instrs.push(bril::Instruction::jump(header_label));
+        self.add(preheader);
+        self.recompute_edges();
+        self.blocks.len() - 1
+    }
+
+    /**
+     * Recomputes `pred` and `succ` after blocks have been added or their
+     * terminators changed.
+     */
+    pub fn recompute_edges(&mut self) {
+        self.pred.clear();
+        self.succ.clear();
+        self.compute_successors();
+    }
+
     pub fn to_instrs(self) -> Vec<bril::Code> {
         return to_instrs(self.blocks);
     }
@@ -0,0 +1,8 @@
+use cs6120::adce;
+use cs6120::bril;
+
+fn main() {
+    let program = bril::load_program();
+    let program = adce::adce(&program);
+    bril::output_program(&program);
+}
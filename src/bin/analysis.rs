@@ -1,16 +1,40 @@
 use cs6120::analysis;
 use cs6120::bb;
 use cs6120::bril;
+use cs6120::cfg::CFG;
+use cs6120::node_splitting;
+use cs6120::v2::dot::Dot;
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let program = bril::load_program();
-    assert_eq!(args.len(), 2);
+    assert!(args.len() == 2 || (args.len() == 3 && args[1] == "dot" && args[2] == "dom"));
     for func in &program.functions {
-        println!("@{}", func.name);
         let analysis = &args[1];
         let blocks = bb::BasicBlocks::from(&func.instrs);
+        if analysis == "dot" {
+            // One digraph per function, which graphviz renders one after another
+            let cfg = CFG::new(&blocks);
+            let mut dot = Dot::new(&cfg)
+                .with_name(format!("@{}", func.name))
+                .with_labels(|idx| format!(".{}", blocks.blocks[idx].label))
+                .with_bodies(|idx| {
+                    blocks.blocks[idx]
+                        .instrs
+                        .iter()
+                        .map(|instr| instr.to_string())
+                        .collect()
+                })
+                .with_back_edges()
+                .with_loops();
+            if args.len() == 3 {
+                dot = dot.with_dominators();
+            }
+            print!("{}", dot);
+            continue;
+        }
+        println!("@{}", func.name);
         if analysis == "const_prop" {
             let constants = analysis::constant_propagation(func.clone());
             for (idx, block) in blocks.blocks.iter().enumerate() {
@@ -59,6 +83,22 @@ fn main() {
                     println!("\t\t\t{}", blocks.blocks[*succ].label);
                 }
             }
+        } else if analysis == "reducibility" {
+            let regions = node_splitting::function_irreducible_regions(func);
+            if regions.is_empty() {
+                println!("\treducible");
+            }
+            for region in regions {
+                println!("\tirreducible");
+                println!("\t\tblocks:");
+                for label in &region.blocks {
+                    println!("\t\t\t{}", label);
+                }
+                println!("\t\tentries:");
+                for label in &region.entries {
+                    println!("\t\t\t{}", label);
+                }
+            }
         } else {
             panic!("Unsupported analysis: {}", analysis);
         }
@@ -0,0 +1,18 @@
+use cs6120::bril;
+use cs6120::inline;
+
+fn main() {
+    // Usage: inline [max-size [max-calls]]
+    let args: Vec<String> = std::env::args().collect();
+    assert!(args.len() <= 3);
+    let mut options = inline::InlineOptions::default();
+    if let Some(max_size) = args.get(1) {
+        options.max_size = max_size.parse().expect("max-size must be an integer");
+    }
+    if let Some(max_calls) = args.get(2) {
+        options.max_calls = max_calls.parse().expect("max-calls must be an integer");
+    }
+    let program = bril::load_program();
+    let program = inline::inline(&program, &options);
+    bril::output_program(&program);
+}
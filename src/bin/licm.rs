@@ -0,0 +1,8 @@
+use cs6120::bril;
+use cs6120::licm;
+
+fn main() {
+    let program = bril::load_program();
+    let program = licm::licm(&program);
+    bril::output_program(&program);
+}
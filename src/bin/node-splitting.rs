@@ -0,0 +1,11 @@
+use cs6120::bril;
+use cs6120::node_splitting;
+
+fn main() {
+    let program = bril::load_program();
+    for region in node_splitting::irreducible_regions(&program) {
+        eprintln!("{}", region);
+    }
+    let program = node_splitting::node_splitting(&program);
+    bril::output_program(&program);
+}
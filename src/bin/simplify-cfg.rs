@@ -0,0 +1,8 @@
+use cs6120::bril;
+use cs6120::simplify_cfg;
+
+fn main() {
+    let program = bril::load_program();
+    let program = simplify_cfg::simplify_cfg(&program);
+    bril::output_program(&program);
+}
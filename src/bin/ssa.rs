@@ -1,10 +1,14 @@
 use cs6120::bril;
 use cs6120::ssa;
 
-fn to_ssa(program: &bril::Program) -> bril::Program {
+fn to_ssa(program: &bril::Program, placement: ssa::PhiPlacement, set_get: bool) -> bril::Program {
     let mut program = program.clone();
     for func in &mut program.functions {
-        let ssa = ssa::SSA::from_function(func);
+        let ssa = if set_get {
+            ssa::SSA::from_function_set_get(func, placement)
+        } else {
+            ssa::SSA::from_function_with(func, placement)
+        };
         *func = ssa.function;
     }
     program
@@ -21,16 +25,92 @@ fn from_ssa(program: &bril::Program) -> bril::Program {
     program
 }
 
+fn convert(program: &bril::Program, set_get: bool) -> bril::Program {
+    let mut program = program.clone();
+    for func in &mut program.functions {
+        let ssa = ssa::SSA {
+            function: func.clone(),
+        };
+        *func = if set_get {
+            ssa.to_set_get().function
+        } else {
+            ssa.to_phis().function
+        };
+    }
+    program
+}
+
+fn repair(program: &bril::Program) -> bril::Program {
+    let mut program = program.clone();
+    for func in &mut program.functions {
+        let ssa = ssa::SSA {
+            function: func.clone(),
+        };
+        *func = ssa.repair().function;
+    }
+    program
+}
+
+fn check(program: &bril::Program) -> bool {
+    let mut valid = true;
+    for func in &program.functions {
+        let ssa = ssa::SSA {
+            function: func.clone(),
+        };
+        if let Err(errors) = ssa.verify() {
+            valid = false;
+            for error in errors {
+                println!("@{}: {}", func.name, error);
+            }
+        }
+    }
+    valid
+}
+
 fn main() {
+    // Usage: ssa to [minimal|semi-pruned|pruned] [phi|set-get] | ssa from
+    //      | ssa convert phi|set-get | ssa check | ssa repair
     let args: Vec<String> = std::env::args().collect();
-    assert_eq!(args.len(), 2);
+    assert!(args.len() >= 2 && args.len() <= 4);
     let mut program = bril::load_program();
+    if args[1] == "check" {
+        assert_eq!(args.len(), 2);
+        if !check(&program) {
+            std::process::exit(1);
+        }
+        println!("ok");
+        return;
+    }
     if args[1] == "to" {
-        program = to_ssa(&program);
+        let mut placement = ssa::PhiPlacement::Minimal;
+        let mut set_get = false;
+        for arg in &args[2..] {
+            match arg.as_str() {
+                "minimal" => placement = ssa::PhiPlacement::Minimal,
+                "semi-pruned" => placement = ssa::PhiPlacement::SemiPruned,
+                "pruned" => placement = ssa::PhiPlacement::Pruned,
+                "phi" => set_get = false,
+                "set-get" => set_get = true,
+                mode => panic!("Unknown phi placement or form {}", mode),
+            }
+        }
+        program = to_ssa(&program, placement, set_get);
     } else if args[1] == "from" {
+        assert_eq!(args.len(), 2);
         program = from_ssa(&program);
+    } else if args[1] == "repair" {
+        assert_eq!(args.len(), 2);
+        program = repair(&program);
+    } else if args[1] == "convert" {
+        assert_eq!(args.len(), 3);
+        let set_get = match args[2].as_str() {
+            "phi" => false,
+            "set-get" => true,
+            form => panic!("Unknown form {}", form),
+        };
+        program = convert(&program, set_get);
     } else {
-        panic!("Command not to/from");
+        panic!("Command not to/from/convert/check/repair");
     }
     bril::output_program(&program);
 }
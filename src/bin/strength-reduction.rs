@@ -0,0 +1,8 @@
+use cs6120::bril;
+use cs6120::strength_reduction;
+
+fn main() {
+    let program = bril::load_program();
+    let program = strength_reduction::strength_reduction(&program);
+    bril::output_program(&program);
+}
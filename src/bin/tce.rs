@@ -0,0 +1,8 @@
+use cs6120::bril;
+use cs6120::tce;
+
+fn main() {
+    let program = bril::load_program();
+    let program = tce::tce(&program);
+    bril::output_program(&program);
+}
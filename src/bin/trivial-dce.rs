@@ -1,8 +1,27 @@
 use cs6120::bril;
 use cs6120::dce;
+use cs6120::v2;
+use cs6120::v2::function::Function;
+use cs6120::v2::instruction::BrilInstruction;
+
+fn v2_dce(program: &bril::Program) -> bril::Program {
+    let mut program = program.clone();
+    for func in &mut program.functions {
+        let mut function = Function::<BrilInstruction>::from_bril(func).unwrap();
+        v2::dce::trivial_dce(&mut function).unwrap();
+        *func = function.to_bril();
+    }
+    program
+}
 
 fn main() {
+    // Usage: trivial-dce [v2]
+    let args: Vec<String> = std::env::args().collect();
     let program = bril::load_program();
-    let program = dce::trivial_dce(&program);
+    let program = match args.get(1).map(String::as_str) {
+        None => dce::trivial_dce(&program),
+        Some("v2") => v2_dce(&program),
+        Some(mode) => panic!("Unknown mode {}", mode),
+    };
     bril::output_program(&program);
-}
\ No newline at end of file
+}
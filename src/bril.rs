@@ -1,6 +1,7 @@
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::fmt;
 use std::io::{self, Read, Write};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -180,6 +181,8 @@ pub enum EffectOps {
     Commit,
     #[cfg(feature = "speculate")]
     Guard,
+    #[cfg(feature = "ssa")]
+    Set,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -201,6 +204,10 @@ pub enum ValueOps {
     Id,
     #[cfg(feature = "ssa")]
     Phi,
+    #[cfg(feature = "ssa")]
+    Get,
+    #[cfg(feature = "ssa")]
+    Undef,
     #[cfg(feature = "float")]
     Fadd,
     #[cfg(feature = "float")]
@@ -269,6 +276,82 @@ pub enum Literal {
     Float(OrderedFloat<f64>),
 }
 
+/// The name of an op in the text format, e.g. `jmp` or `add`.
+fn op_name<T: Serialize>(op: &T) -> String {
+    match serde_json::to_value(op).unwrap() {
+        serde_json::Value::String(name) => name,
+        _ => unreachable!(),
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            #[cfg(feature = "float")]
+            Type::Float => write!(f, "float"),
+            #[cfg(feature = "memory")]
+            Type::Pointer(t) => write!(f, "ptr<{}>", t),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Int(v) => write!(f, "{}", v),
+            Literal::Bool(v) => write!(f, "{}", v),
+            #[cfg(feature = "float")]
+            Literal::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Formats the instruction the way `bril2txt` does.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, args, funcs, labels) = match self {
+            Instruction::Constant {
+                op,
+                dest,
+                const_type,
+                value,
+            } => {
+                return write!(f, "{}: {} = {} {};", dest, const_type, op_name(op), value);
+            }
+            Instruction::Value {
+                op,
+                dest,
+                op_type,
+                args,
+                funcs,
+                labels,
+            } => {
+                write!(f, "{}: {} = ", dest, op_type)?;
+                (op_name(op), args, funcs, labels)
+            }
+            Instruction::Effect {
+                op,
+                args,
+                funcs,
+                labels,
+            } => (op_name(op), args, funcs, labels),
+        };
+        write!(f, "{}", op)?;
+        for func in funcs {
+            write!(f, " @{}", func)?;
+        }
+        for arg in args {
+            write!(f, " {}", arg)?;
+        }
+        for label in labels {
+            write!(f, " .{}", label)?;
+        }
+        write!(f, ";")
+    }
+}
+
 pub fn load_program() -> Program {
     let mut buffer = String::new();
     io::stdin().read_to_string(&mut buffer).unwrap();
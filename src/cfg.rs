@@ -1,6 +1,10 @@
 use super::bb;
 use super::bril;
-use std::collections::{HashMap, HashSet, BTreeSet};
+use super::util;
+use super::v2::dominance_tree;
+use super::v2::graph::Graph;
+use super::v2::loops;
+use std::collections::BTreeSet;
 use std::fmt::{Debug, Formatter};
 
 #[derive(Copy, Clone)]
@@ -42,27 +46,44 @@ pub struct CFGIter<'a> {
     end: usize,
 }
 
+// Depth first search with an explicit stack, so that functions with a huge
+// number of blocks don't overflow the call stack. Each entry on the stack is a
+// block and the position of the next successor to visit.
 fn iterate(
     output: &mut Vec<usize>,
-    visited: &mut Vec<bool>,
+    visited: &mut [bool],
     blocks: &bb::BasicBlocks,
     order: IterOrder,
-    idx: usize,
+    root: usize,
 ) {
-    if visited[idx] {
+    if visited[root] {
         return;
     }
-    visited[idx] = true;
-    match order {
-        IterOrder::InOrder => output.push(idx),
-        IterOrder::PostOrder => {}
-    }
-    for child in &blocks.succ[idx] {
-        iterate(output, visited, blocks, order, *child);
-    }
-    match order {
-        IterOrder::InOrder => {}
-        IterOrder::PostOrder => output.push(idx),
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    if let IterOrder::InOrder = order {
+        output.push(root);
+    }
+    while let Some((idx, next)) = stack.last_mut() {
+        let idx = *idx;
+        match blocks.succ[idx].get(*next) {
+            Some(&child) => {
+                *next += 1;
+                if !visited[child] {
+                    visited[child] = true;
+                    if let IterOrder::InOrder = order {
+                        output.push(child);
+                    }
+                    stack.push((child, 0));
+                }
+            }
+            None => {
+                stack.pop();
+                if let IterOrder::PostOrder = order {
+                    output.push(idx);
+                }
+            }
+        }
     }
 }
 
@@ -172,6 +193,10 @@ impl<'a> CFG<'a> {
         CFGIter::new(*self, IterOrder::PostOrder)
     }
 
+    pub fn reverse_post_order_iter(&self) -> std::iter::Rev<CFGIter<'a>> {
+        self.post_order_iter().rev()
+    }
+
     pub fn in_order_iter(&self) -> CFGIter<'a> {
         CFGIter::new(*self, IterOrder::InOrder)
     }
@@ -185,56 +210,41 @@ impl<'a> CFG<'a> {
     }
 }
 
-fn dominators(cfg: CFG) -> HashMap<usize, HashSet<usize>> {
-    let all: HashSet<_> = (0usize..cfg.len()).collect();
-    let mut dom: HashMap<_, _> = (0usize..cfg.len()).map(|b| (b, all.clone())).collect();
-
-    loop {
-        let mut changed = false;
-        for block in cfg.post_order_iter().rev() {
-            let self_dom: HashSet<usize> = vec![block.idx()].into_iter().collect();
-            let mut pred_iter = block.pred().iter();
-            let block_dom = pred_iter.next();
-            let block_dom = match block_dom {
-                None => HashSet::new(),
-                Some(block_dom) => pred_iter.fold(dom[block_dom].clone(), |block_dom, pred| {
-                    &block_dom & &dom[pred]
-                }),
-            };
-            let block_dom = &block_dom | &self_dom;
-            changed |= block_dom != dom[&block.idx()];
-            dom.insert(block.idx(), block_dom);
-        }
-        if !changed {
-            break;
-        }
-    }
-
-    dom
-}
-
-fn strictly_dominates(cfg: CFG) -> HashMap<usize, HashSet<usize>> {
-    let dominators = dominators(cfg);
-    let mut dominates: HashMap<_, _> = (0usize..cfg.len()).map(|b| (b, HashSet::new())).collect();
-
-    for (vertex, dominators) in &dominators {
-        for dominator in dominators {
-            dominates.get_mut(dominator).unwrap().insert(*vertex);
+// Numbers the blocks in pre and post order of a walk over the tree, so a block
+// dominates exactly the blocks numbered within its own range.
+fn number(tree: &[BTreeSet<usize>]) -> (Vec<usize>, Vec<usize>) {
+    let mut pre = vec![0; tree.len()];
+    let mut post = vec![0; tree.len()];
+    let mut is_root = vec![true; tree.len()];
+    for &child in tree.iter().flatten() {
+        is_root[child] = false;
+    }
+    let (mut next_pre, mut next_post) = (0, 0);
+    for root in (0..tree.len()).filter(|&idx| is_root[idx]) {
+        // Each block is pushed twice, the second time to number it on the way out
+        let mut stack = vec![(root, false)];
+        while let Some((idx, visited)) = stack.pop() {
+            if visited {
+                post[idx] = next_post;
+                next_post += 1;
+                continue;
+            }
+            pre[idx] = next_pre;
+            next_pre += 1;
+            stack.push((idx, true));
+            stack.extend(tree[idx].iter().map(|&child| (child, false)));
         }
     }
-    for (vertex, dominates) in &mut dominates {
-        dominates.remove(vertex);
-    }
-    // eprintln!("dominators = {:?}", dominators);
-    // eprintln!("dominates = {:?}", dominates);
-
-    dominates
+    (pre, post)
 }
 
 #[derive(Debug)]
 pub struct DominanceTree<'a> {
     cfg: CFG<'a>,
     tree: Vec<BTreeSet<usize>>,
+    // Pre and post order numbers of each block in the tree
+    pre: Vec<usize>,
+    post: Vec<usize>,
 }
 
 pub struct DominatedIterator<'c, 'd> {
@@ -256,42 +266,39 @@ impl<'c, 'd> Iterator for DominatedIterator<'c, 'd> {
 
 impl<'c> DominanceTree<'c> {
     pub fn new(cfg: CFG) -> DominanceTree {
-        let dom = strictly_dominates(cfg);
-        let mut tree: Vec<_> = std::iter::repeat(BTreeSet::new()).take(cfg.len()).collect();
-        for (idx, set) in tree.iter_mut().enumerate() {
-            let mut imm_dom: HashSet<_> = dom[&idx].clone();
-            for dominated in &dom[&idx] {
-                let mut dom: HashSet<_> = dom[&dominated].clone();
-                dom.remove(&dominated);
-                imm_dom = &imm_dom - &dom;
-            }
-            set.extend(imm_dom.iter());
+        // Unreachable blocks are left as roots of their own
+        let mut tree = vec![BTreeSet::new(); cfg.len()];
+        for (idx, dominator) in dominance_tree::immediate_dominators(&cfg) {
+            tree[dominator].insert(idx);
+        }
+        let (pre, post) = number(&tree);
+        DominanceTree {
+            cfg,
+            tree,
+            pre,
+            post,
         }
-        DominanceTree { cfg, tree }
+    }
+
+    pub fn dominates(&self, dominator: usize, dominated: usize) -> bool {
+        self.pre[dominator] <= self.pre[dominated] && self.post[dominated] <= self.post[dominator]
+    }
+
+    pub fn strictly_dominates(&self, dominator: usize, dominated: usize) -> bool {
+        dominator != dominated && self.dominates(dominator, dominated)
     }
 
     pub fn dominance_frontier(
         &'c self,
         idx: usize,
     ) -> impl Iterator<Item = (Block<'c>, Block<'c>)> {
-        // For now just use an inefficient solution.
-        // Can be replaced with a more efficient impl without changing callers.
-        // TODO: Replace me
-        let strictly_dominated: HashSet<_> = self.strictly_dominated(idx).collect();
         let cfg = self.cfg;
         self.dominated(idx)
             .flat_map(|b| b.succ().iter().map(move |s| (b, s)))
             .map(move |(b, &s)| (b, cfg.get_block(s)))
-            .filter(move |(_, s)| !strictly_dominated.contains(s))
+            .filter(move |(_, s)| !self.strictly_dominates(idx, s.idx()))
             .collect::<Vec<_>>()
             .into_iter()
-        // self.tree[idx]
-        //     .iter()
-        //     .flat_map(|&dom| self.tree[dom].iter())
-        //     .filter(|&sub_dom| !self.tree[idx].contains(sub_dom))
-        //     .map(|&i| self.cfg.get_block(i))
-        //     .collect::<Vec<_>>()
-        //     .into_iter()
     }
 
     pub fn immediately_dominated(&self, idx: usize) -> impl Iterator<Item = Block<'c>> {
@@ -316,3 +323,116 @@ impl<'c> DominanceTree<'c> {
         }
     }
 }
+
+impl<'a> Graph for CFG<'a> {
+    type Node = usize;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        if self.len() == 0 {
+            None
+        } else {
+            Some(0)
+        }
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        // Blocks may return from anywhere, so there is no single exit block
+        None
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        0..self.len()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.blocks.pred[node].iter().copied()
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.blocks.succ[node].iter().copied()
+    }
+}
+
+/**
+ * Gives every loop header a preheader: a block whose only successor is the
+ * header and which is the header's only predecessor from outside the loop.
+ */
+pub fn insert_preheaders(blocks: bb::BasicBlocks) -> bb::BasicBlocks {
+    let mut blocks = blocks;
+    let mut plans = Vec::new();
+    {
+        let cfg = CFG::new(&blocks);
+        let dom = dominance_tree::DominanceTree::new(&cfg);
+        for natural_loop in loops::natural_loops(&cfg, &dom) {
+            let header = natural_loop.header();
+            if natural_loop.preheader(&cfg).is_some() {
+                continue;
+            }
+            let outside: Vec<_> = natural_loop.entering_nodes(&cfg).collect();
+            let has_phis = cfg
+                .get_block(header)
+                .instrs()
+                .iter()
+                .any(|instr| util::is_value_op(instr, bril::ValueOps::Phi));
+            if header == 0 || (has_phis && outside.len() != 1) {
+                // Merging phi arguments isn't supported, leave the loop alone
+                continue;
+            }
+            plans.push((header, outside));
+        }
+    }
+    for (header, outside) in plans {
+        blocks.insert_preheader(header, &outside);
+    }
+    blocks
+}
+
+pub struct Loop {
+    pub header: usize,
+    pub preheader: usize,
+    /// Sorted
+    pub blocks: Vec<usize>,
+    /// Blocks that may leave the loop, either by a branch or a return
+    pub exiting: Vec<usize>,
+    /// Blocks outside the loop that are jumped to from inside the loop
+    pub exits: Vec<usize>,
+}
+
+impl Loop {
+    pub fn contains(&self, idx: usize) -> bool {
+        self.blocks.binary_search(&idx).is_ok()
+    }
+}
+
+/**
+ * Finds the loops that have a preheader, innermost loops first so that code
+ * moved out of a loop can be moved again by the enclosing loop.
+ */
+pub fn find_loops(blocks: &bb::BasicBlocks) -> Vec<Loop> {
+    let cfg = CFG::new(blocks);
+    let dom = dominance_tree::DominanceTree::new(&cfg);
+    let forest = loops::LoopForest::new(&cfg, &dom);
+    forest
+        .inner_to_outer()
+        .into_iter()
+        .map(|idx| forest.get(idx))
+        .filter_map(|natural_loop| {
+            let preheader = natural_loop.preheader(&cfg)?;
+            let mut loop_blocks: Vec<_> = natural_loop.nodes().collect();
+            loop_blocks.sort();
+            let mut exiting = natural_loop.exiting_nodes(&cfg);
+            exiting.extend(
+                loop_blocks
+                    .iter()
+                    .filter(|&&block| cfg.successors(block).next().is_none()),
+            );
+            Some(Loop {
+                header: natural_loop.header(),
+                preheader,
+                blocks: loop_blocks,
+                exiting,
+                exits: natural_loop.exits(&cfg),
+            })
+        })
+        .collect()
+}
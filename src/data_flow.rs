@@ -70,16 +70,13 @@ pub fn data_flow<Algo: DataFlowAlgorithm>(
             algo.merge(predecessors(&blocks, idx, &direction).map(|idx| &output[*idx]));
         let block_output = algo.transfer(idx, &blocks.blocks[idx], &block_input);
         let changed = output[idx] != block_output;
-        eprintln!("{}: {:?} -> {:?}", blocks.blocks[idx].label, block_input, block_output);
 
         input[idx] = Some(block_input);
         output[idx] = block_output;
-        eprintln!("\t{:?}", worklist);
         worklist.remove(&idx);
         if changed {
             worklist.extend(successors(&blocks, idx, &direction));
         }
-        eprintln!("\t{:?}", worklist);
     }
 
     // for idx in 0usize..blocks.blocks.len() {
@@ -0,0 +1,281 @@
+use super::v2::algorithms::StronglyConnectedComponents;
+use super::v2::graph::Graph;
+use super::{bril, util};
+use std::collections::HashMap;
+
+/// Limits on which call sites get inlined.
+#[derive(Debug, Clone, Copy)]
+pub struct InlineOptions {
+    /// Largest callee, in instructions, that is inlined
+    pub max_size: usize,
+    /// Callees with more call sites than this in the program are not inlined
+    pub max_calls: usize,
+}
+
+impl Default for InlineOptions {
+    fn default() -> Self {
+        InlineOptions {
+            max_size: 32,
+            max_calls: 8,
+        }
+    }
+}
+
+fn get_call(instr: &bril::Instruction) -> Option<&String> {
+    match instr {
+        bril::Instruction::Value {
+            op: bril::ValueOps::Call,
+            funcs,
+            ..
+        }
+        | bril::Instruction::Effect {
+            op: bril::EffectOps::Call,
+            funcs,
+            ..
+        } => funcs.first(),
+        _ => None,
+    }
+}
+
+fn get_args_mut(instr: &mut bril::Instruction) -> Option<&mut Vec<String>> {
+    match instr {
+        bril::Instruction::Constant { .. } => None,
+        bril::Instruction::Value { args, .. } => Some(args),
+        bril::Instruction::Effect { args, .. } => Some(args),
+    }
+}
+
+fn size(function: &bril::Function) -> usize {
+    function.instrs.iter().filter_map(util::get_instr).count()
+}
+
+fn callees<'a>(
+    function: &'a bril::Function,
+    indices: &'a HashMap<&String, usize>,
+) -> impl Iterator<Item = usize> + 'a {
+    function
+        .instrs
+        .iter()
+        .filter_map(util::get_instr)
+        .filter_map(get_call)
+        .filter_map(move |callee| indices.get(callee).copied())
+}
+
+/// Functions as nodes, with an edge from each caller to each of its callees.
+struct CallGraph<'a> {
+    calls: &'a [Vec<usize>],
+}
+
+impl<'a> Graph for CallGraph<'a> {
+    type Node = usize;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        None
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        None
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        0..self.calls.len()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        (0..self.calls.len()).filter(move |caller| self.calls[*caller].contains(&node))
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.calls[node].iter().copied()
+    }
+}
+
+/// Renames the variables and labels of one inlined copy of a callee. Every name
+/// gets a prefix with more underscores than any name in the caller or callee,
+/// followed by a number unique to the call site, so nothing can clash.
+struct Renamer<'a> {
+    prefix: &'a str,
+    site: usize,
+}
+
+impl<'a> Renamer<'a> {
+    fn rename(&self, name: &str) -> String {
+        format!("{}inline{}.{}", self.prefix, self.site, name)
+    }
+
+    fn return_label(&self) -> String {
+        format!("{}inline{}", self.prefix, self.site)
+    }
+
+    fn rename_instr(&self, instr: &mut bril::Instruction) {
+        if let bril::Instruction::Constant { dest, .. } | bril::Instruction::Value { dest, .. } =
+            instr
+        {
+            *dest = self.rename(dest);
+        }
+        if let Some(args) = get_args_mut(instr) {
+            for arg in args {
+                *arg = self.rename(arg);
+            }
+        }
+        if let Some(labels) = util::get_labels_mut(instr) {
+            for label in labels {
+                *label = self.rename(label);
+            }
+        }
+    }
+}
+
+/// Expands the call `call` of `callee` in place, appending the code to `instrs`.
+fn inline_call(
+    instrs: &mut Vec<bril::Code>,
+    call: &bril::Instruction,
+    callee: &bril::Function,
+    renamer: &Renamer,
+) {
+    let return_label = renamer.return_label();
+    let dest = match call {
+        bril::Instruction::Value { dest, op_type, .. } => Some((dest, op_type)),
+        _ => None,
+    };
+    for (param, arg) in callee.args.iter().zip(call.args()) {
+        instrs.push(bril::Code::Instruction(bril::Instruction::id(
+            param.arg_type.clone(),
+            renamer.rename(&param.name),
+            arg.clone(),
+        )));
+    }
+    for (idx, code) in callee.instrs.iter().enumerate() {
+        match code {
+            bril::Code::Label { label } => instrs.push(bril::Code::Label {
+                label: renamer.rename(label),
+            }),
+            bril::Code::Instruction(instr) if instr.is_return() => {
+                if let (Some((dest, op_type)), Some(value)) = (dest, instr.args().first()) {
+                    instrs.push(bril::Code::Instruction(bril::Instruction::id(
+                        op_type.clone(),
+                        dest.clone(),
+                        renamer.rename(value),
+                    )));
+                }
+                // The last return falls through to the return label
+                if idx + 1 != callee.instrs.len() {
+                    instrs.push(bril::Code::Instruction(bril::Instruction::jump(
+                        return_label.clone(),
+                    )));
+                }
+            }
+            bril::Code::Instruction(instr) => {
+                let mut instr = instr.clone();
+                renamer.rename_instr(&mut instr);
+                instrs.push(bril::Code::Instruction(instr));
+            }
+        }
+    }
+    instrs.push(bril::Code::Label {
+        label: return_label,
+    });
+}
+
+fn inline_function(
+    function: &bril::Function,
+    functions: &[bril::Function],
+    indices: &HashMap<&String, usize>,
+    should_inline: impl Fn(usize) -> bool,
+) -> bril::Function {
+    let callees: Vec<_> = callees(function, indices)
+        .filter(|&callee| should_inline(callee))
+        .collect();
+    if callees.is_empty() {
+        return function.clone();
+    }
+    let max = callees
+        .iter()
+        .map(|&callee| util::max_underscores(&functions[callee]))
+        .chain(std::iter::once(util::max_underscores(function)))
+        .max()
+        .unwrap();
+    let prefix = "_".repeat(max + 1);
+
+    let mut inlined = function.clone();
+    inlined.instrs = Vec::new();
+    let mut site = 0;
+    // The code after a call continues under its return label, so the last
+    // one in each block becomes the predecessor the block's successors see
+    let mut block = None;
+    let mut ends = HashMap::new();
+    for code in &function.instrs {
+        if let bril::Code::Label { label } = code {
+            block = Some(label);
+        }
+        let callee = util::get_instr(code)
+            .and_then(get_call)
+            .and_then(|callee| indices.get(callee).copied())
+            .filter(|&callee| should_inline(callee));
+        match callee {
+            Some(callee) => {
+                let renamer = Renamer {
+                    prefix: &prefix,
+                    site,
+                };
+                let call = util::get_instr(code).unwrap();
+                inline_call(&mut inlined.instrs, call, &functions[callee], &renamer);
+                if let Some(block) = block {
+                    ends.insert(block, renamer.return_label());
+                }
+                site += 1;
+            }
+            None => inlined.instrs.push(code.clone()),
+        }
+    }
+    for code in &mut inlined.instrs {
+        if let bril::Code::Instruction(instr) = code {
+            if util::is_value_op(instr, bril::ValueOps::Phi) {
+                for label in util::get_labels_mut(instr).unwrap() {
+                    if let Some(end) = ends.get(label) {
+                        *label = end.clone();
+                    }
+                }
+            }
+        }
+    }
+    inlined
+}
+
+/// Inlines calls to small, non-recursive functions. Functions are processed
+/// callees first, so a callee has already had its own calls inlined.
+pub fn inline(program: &bril::Program, options: &InlineOptions) -> bril::Program {
+    let indices: HashMap<_, _> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(idx, function)| (&function.name, idx))
+        .collect();
+    let calls: Vec<Vec<usize>> = program
+        .functions
+        .iter()
+        .map(|function| callees(function, &indices).collect())
+        .collect();
+    let mut call_sites = vec![0; program.functions.len()];
+    for &callee in calls.iter().flatten() {
+        call_sites[callee] += 1;
+    }
+    // The components come callees first, so every function comes after
+    // everything it (non-recursively) calls
+    let scc = StronglyConnectedComponents::new(&CallGraph { calls: &calls });
+    let recursive: Vec<_> = (0..program.functions.len())
+        .map(|function| scc.is_cyclic(scc.component(function)))
+        .collect();
+
+    let mut functions = program.functions.clone();
+    for &function in scc.components().iter().flatten() {
+        let should_inline = |callee: usize| {
+            !recursive[callee]
+                && call_sites[callee] <= options.max_calls
+                && size(&functions[callee]) <= options.max_size
+        };
+        functions[function] =
+            inline_function(&functions[function], &functions, &indices, should_inline);
+    }
+    bril::Program { functions }
+}
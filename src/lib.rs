@@ -1,5 +1,12 @@
 pub mod bril;
 pub mod dce;
+pub mod adce;
+pub mod licm;
+pub mod strength_reduction;
+pub mod inline;
+pub mod tce;
+pub mod simplify_cfg;
+pub mod node_splitting;
 pub mod bb;
 pub mod lvn;
 pub mod data_flow;
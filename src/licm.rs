@@ -0,0 +1,308 @@
+use super::analysis;
+use super::cfg::{find_loops, insert_preheaders, Loop, CFG};
+use super::v2::dominance_tree::DominanceTree;
+use super::{bb, bril, util};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Origin {
+    Alloc(usize, usize),
+    Unknown,
+}
+
+// Flow-insensitive approximation of which allocation each pointer variable may point into
+fn pointer_origins(blocks: &bb::BasicBlocks) -> HashMap<String, HashSet<Origin>> {
+    let mut origins: HashMap<String, HashSet<Origin>> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (block, bb) in blocks.blocks.iter().enumerate() {
+            for (idx, instr) in bb.instrs.iter().enumerate() {
+                let (op, dest, args) = match instr {
+                    bril::Instruction::Value {
+                        op,
+                        dest,
+                        args,
+                        op_type: bril::Type::Pointer(_),
+                        ..
+                    } => (op, dest, args),
+                    _ => continue,
+                };
+                let new: HashSet<Origin> = match op {
+                    bril::ValueOps::Alloc => HashSet::from([Origin::Alloc(block, idx)]),
+                    // Offsets and copies point into the same allocation as their base
+                    bril::ValueOps::PtrAdd | bril::ValueOps::Id => origins_of(&origins, &args[..1]),
+                    bril::ValueOps::Phi => origins_of(&origins, args),
+                    _ => HashSet::from([Origin::Unknown]),
+                };
+                let old = origins.entry(dest.clone()).or_default();
+                let len = old.len();
+                old.extend(new);
+                changed |= old.len() != len;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    origins
+}
+
+fn origins_of(origins: &HashMap<String, HashSet<Origin>>, args: &[String]) -> HashSet<Origin> {
+    args.iter()
+        .flat_map(|arg| {
+            origins
+                .get(arg)
+                .cloned()
+                .unwrap_or_else(|| HashSet::from([Origin::Unknown]))
+        })
+        .collect()
+}
+
+fn may_alias(origins: &HashMap<String, HashSet<Origin>>, a: &String, b: &String) -> bool {
+    match (origins.get(a), origins.get(b)) {
+        (Some(a), Some(b)) => {
+            a.contains(&Origin::Unknown) || b.contains(&Origin::Unknown) || !a.is_disjoint(b)
+        }
+        _ => true,
+    }
+}
+
+// Whether the instruction computes the same value from the same arguments
+// without side effects. Returns whether it may trap if so.
+fn pure_op(instr: &bril::Instruction) -> Option<bool> {
+    match instr {
+        bril::Instruction::Constant { .. } => Some(false),
+        bril::Instruction::Value { op, .. } => match op {
+            bril::ValueOps::Call
+            | bril::ValueOps::Phi
+            | bril::ValueOps::Get
+            | bril::ValueOps::Alloc => None,
+            bril::ValueOps::Div | bril::ValueOps::Load => Some(true),
+            _ => Some(false),
+        },
+        bril::Instruction::Effect { .. } => None,
+    }
+}
+
+fn has_effect(instr: &bril::Instruction) -> bool {
+    match instr {
+        bril::Instruction::Constant { .. } => false,
+        bril::Instruction::Value { op, .. } => *op == bril::ValueOps::Call,
+        bril::Instruction::Effect { op, .. } => !matches!(
+            op,
+            bril::EffectOps::Jump | bril::EffectOps::Branch | bril::EffectOps::Nop
+        ),
+    }
+}
+
+fn is_effect_op(instr: &bril::Instruction, expect: bril::EffectOps) -> bool {
+    match instr {
+        bril::Instruction::Effect { op, .. } => *op == expect,
+        _ => false,
+    }
+}
+
+struct Hoister<'a> {
+    blocks: &'a bb::BasicBlocks,
+    natural_loop: &'a Loop,
+    dom: &'a DominanceTree<'a, CFG<'a>>,
+    origins: &'a HashMap<String, HashSet<Origin>>,
+    live_in: Vec<HashSet<String>>,
+    defs: HashMap<&'a String, usize>,
+    uses: HashMap<&'a String, Vec<(usize, usize, bool)>>,
+    stores: Vec<&'a String>,
+    clobbers_memory: bool,
+    /// Instructions in the loop with an observable effect, including leaving it by a return
+    effects: Vec<(usize, usize)>,
+    hoisted: HashSet<(usize, usize)>,
+    hoisted_vars: HashSet<&'a String>,
+    order: Vec<(usize, usize)>,
+}
+
+impl<'a> Hoister<'a> {
+    fn new(
+        blocks: &'a bb::BasicBlocks,
+        natural_loop: &'a Loop,
+        dom: &'a DominanceTree<'a, CFG<'a>>,
+        origins: &'a HashMap<String, HashSet<Origin>>,
+    ) -> Self {
+        let (_, live_in) = analysis::live_variables(blocks);
+        let mut defs = HashMap::new();
+        let mut uses = HashMap::new();
+        let mut stores = Vec::new();
+        let mut clobbers_memory = false;
+        let mut effects = Vec::new();
+        for &block in &natural_loop.blocks {
+            for (idx, instr) in blocks.blocks[block].instrs.iter().enumerate() {
+                if let Some(dest) = util::get_dest(instr) {
+                    *defs.entry(dest).or_insert(0) += 1;
+                }
+                let is_phi = util::is_value_op(instr, bril::ValueOps::Phi);
+                for arg in instr.args() {
+                    uses.entry(arg)
+                        .or_insert_with(Vec::new)
+                        .push((block, idx, is_phi));
+                }
+                if is_effect_op(instr, bril::EffectOps::Store) {
+                    stores.push(&instr.args()[0]);
+                }
+                clobbers_memory |= is_effect_op(instr, bril::EffectOps::Call)
+                    || is_effect_op(instr, bril::EffectOps::Free)
+                    || util::is_value_op(instr, bril::ValueOps::Call);
+                if has_effect(instr) {
+                    effects.push((block, idx));
+                }
+            }
+        }
+        Hoister {
+            blocks,
+            natural_loop,
+            dom,
+            origins,
+            live_in,
+            defs,
+            uses,
+            stores,
+            clobbers_memory,
+            effects,
+            hoisted: HashSet::new(),
+            hoisted_vars: HashSet::new(),
+            order: Vec::new(),
+        }
+    }
+
+    fn dominates(&self, a: usize, b: usize) -> bool {
+        self.dom.dominates(a, b)
+    }
+
+    fn is_invariant(&self, arg: &String) -> bool {
+        !self.defs.contains_key(arg) || self.hoisted_vars.contains(arg)
+    }
+
+    fn can_hoist(&self, block: usize, idx: usize) -> bool {
+        let instr = &self.blocks.blocks[block].instrs[idx];
+        let may_trap = match pure_op(instr) {
+            Some(may_trap) => may_trap,
+            None => return false,
+        };
+        let dest = util::unwrap_dest(instr);
+        if self.defs[dest] != 1 || !instr.args().iter().all(|arg| self.is_invariant(arg)) {
+            return false;
+        }
+        if util::is_value_op(instr, bril::ValueOps::Load) {
+            let ptr = &instr.args()[0];
+            if self.clobbers_memory
+                || self
+                    .stores
+                    .iter()
+                    .any(|store| may_alias(self.origins, store, ptr))
+            {
+                return false;
+            }
+        }
+        // Every use in the loop must see this definition
+        let uses = self.uses.get(dest).map(|uses| uses.as_slice()).unwrap_or(&[]);
+        let dominates_uses = uses.iter().all(|&(use_block, use_idx, is_phi)| {
+            !is_phi
+                && if use_block == block {
+                    use_idx > idx
+                } else {
+                    self.dominates(block, use_block)
+                }
+        });
+        if !dominates_uses {
+            return false;
+        }
+        // A trap moved to the preheader fires before every effect in the loop. That is
+        // only unobservable if the instruction is in the header ahead of all of them, so
+        // that it would have trapped first anyway.
+        let header = self.natural_loop.header;
+        if may_trap
+            && self.effects.iter().any(|&(effect_block, effect_idx)| {
+                block != header || (effect_block == header && effect_idx < idx)
+            })
+        {
+            return false;
+        }
+        // Either the definition always runs before the loop is left, or it is
+        // safe to speculate because nothing after the loop can observe it
+        let dominates_exits = self
+            .natural_loop
+            .exiting
+            .iter()
+            .all(|&exiting| self.dominates(block, exiting));
+        dominates_exits
+            || (!may_trap
+                && self
+                    .natural_loop
+                    .exits
+                    .iter()
+                    .all(|&exit| !self.live_in[exit].contains(dest)))
+    }
+
+    fn compute(&mut self) {
+        loop {
+            let mut changed = false;
+            for &block in &self.natural_loop.blocks {
+                for idx in 0..self.blocks.blocks[block].instrs.len() {
+                    if !self.hoisted.contains(&(block, idx)) && self.can_hoist(block, idx) {
+                        let dest = util::unwrap_dest(&self.blocks.blocks[block].instrs[idx]);
+                        self.hoisted.insert((block, idx));
+                        self.hoisted_vars.insert(dest);
+                        self.order.push((block, idx));
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+fn function_licm(blocks: bb::BasicBlocks) -> bb::BasicBlocks {
+    let mut blocks = insert_preheaders(blocks);
+    let loops = find_loops(&blocks);
+    let origins = pointer_origins(&blocks);
+    for natural_loop in &loops {
+        let order = {
+            // Hoisting only moves instructions, but the tree can't outlive the borrow
+            let cfg = CFG::new(&blocks);
+            let dom = DominanceTree::new(&cfg);
+            let mut hoister = Hoister::new(&blocks, natural_loop, &dom, &origins);
+            hoister.compute();
+            hoister.order
+        };
+        if order.is_empty() {
+            continue;
+        }
+        let hoisted: Vec<_> = order
+            .iter()
+            .map(|&(block, idx)| blocks.blocks[block].instrs[idx].clone())
+            .collect();
+        for &block in &natural_loop.blocks {
+            let instrs = std::mem::take(&mut blocks.blocks[block].instrs);
+            blocks.blocks[block].instrs = instrs
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| !order.contains(&(block, *idx)))
+                .map(|(_, instr)| instr)
+                .collect();
+        }
+        let preheader = &mut blocks.blocks[natural_loop.preheader].instrs;
+        let terminator = preheader.pop().unwrap();
+        preheader.extend(hoisted);
+        preheader.push(terminator);
+    }
+    blocks
+}
+
+pub fn licm(program: &bril::Program) -> bril::Program {
+    let mut licm_program = program.clone();
+    for func in &mut licm_program.functions {
+        let blocks = bb::BasicBlocks::from(&func.instrs);
+        func.instrs = function_licm(blocks).to_instrs();
+    }
+    licm_program
+}
@@ -0,0 +1,224 @@
+use super::cfg::CFG;
+use super::v2::graph::Graph;
+use super::v2::reducibility::LimitGraph;
+use super::{bb, bril, util};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A cycle in a function that can be entered at more than one block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrreducibleRegion {
+    pub function: String,
+    /// Every block collapsed into the region by the T1/T2 reductions
+    pub blocks: Vec<String>,
+    /// The blocks control can enter the region at
+    pub entries: Vec<String>,
+}
+
+impl fmt::Display for IrreducibleRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let labels = |labels: &[String]| {
+            labels
+                .iter()
+                .map(|label| format!(".{}", label))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "@{}: irreducible region {} entered at {}",
+            self.function,
+            labels(&self.blocks),
+            labels(&self.entries)
+        )
+    }
+}
+
+/// Finds the irreducible regions of a function, empty if it is reducible.
+pub fn function_irreducible_regions(function: &bril::Function) -> Vec<IrreducibleRegion> {
+    let blocks = bb::BasicBlocks::from(&function.instrs);
+    let cfg = CFG::new(&blocks);
+    let labels = |nodes: &[usize]| {
+        nodes
+            .iter()
+            .map(|&node| blocks.blocks[node].label.clone())
+            .collect()
+    };
+    LimitGraph::new(&cfg)
+        .irreducible_regions()
+        .iter()
+        .map(|region| IrreducibleRegion {
+            function: function.name.clone(),
+            blocks: labels(region.nodes()),
+            entries: labels(region.entries()),
+        })
+        .collect()
+}
+
+/// Finds the irreducible regions of every function, empty if the whole
+/// program is reducible.
+pub fn irreducible_regions(program: &bril::Program) -> Vec<IrreducibleRegion> {
+    program
+        .functions
+        .iter()
+        .flat_map(function_irreducible_regions)
+        .collect()
+}
+
+fn phi_args_mut(instr: &mut bril::Instruction) -> Option<(&mut Vec<String>, &mut Vec<String>)> {
+    match instr {
+        bril::Instruction::Value {
+            op: bril::ValueOps::Phi,
+            args,
+            labels,
+            ..
+        } => Some((args, labels)),
+        _ => None,
+    }
+}
+
+fn rename_labels(instr: &mut bril::Instruction, renames: &HashMap<String, String>) {
+    if let Some(labels) = util::get_labels_mut(instr) {
+        for label in labels.iter_mut() {
+            if let Some(new) = renames.get(label) {
+                *label = new.clone();
+            }
+        }
+    }
+}
+
+/// Keeps only the phi arguments whose label passes `keep`.
+fn filter_phi_args(block: &mut bb::BasicBlock, keep: impl Fn(&String) -> bool) {
+    for instr in block.instrs.iter_mut() {
+        if let Some((args, labels)) = phi_args_mut(instr) {
+            let (new_args, new_labels) = args
+                .iter()
+                .zip(labels.iter())
+                .filter(|(_, label)| keep(label))
+                .map(|(arg, label)| (arg.clone(), label.clone()))
+                .unzip();
+            *args = new_args;
+            *labels = new_labels;
+        }
+    }
+}
+
+/// Copies the blocks of `group` and points the edges from `preds` into its
+/// header at the copy. Edges leaving the group are kept, so phis outside the
+/// group gain an argument for each copied predecessor. The copies define the
+/// same variables as the originals, so SSA form needs to be repaired after.
+fn split(blocks: &mut bb::BasicBlocks, group: &[usize], preds: &[usize]) {
+    let header = blocks.blocks[group[0]].label.clone();
+    let mut copies = HashMap::new();
+    let mut copy_idxs = Vec::new();
+    for &block in group {
+        let mut copy = blocks.blocks[block].clone();
+        copy.label = blocks.create_label();
+        copies.insert(blocks.blocks[block].label.clone(), copy.label.clone());
+        copy_idxs.push(blocks.blocks.len());
+        blocks.add(copy);
+    }
+
+    // Edges within the group stay within the copy
+    for &idx in &copy_idxs {
+        for instr in blocks.blocks[idx].instrs.iter_mut() {
+            rename_labels(instr, &copies);
+        }
+    }
+    let pred_labels: Vec<_> = preds
+        .iter()
+        .map(|&pred| blocks.blocks[pred].label.clone())
+        .collect();
+    let copied_header = copies[&header].clone();
+    filter_phi_args(&mut blocks.blocks[copy_idxs[0]], |label| {
+        pred_labels.contains(label) || copies.values().any(|copy| copy == label)
+    });
+    filter_phi_args(&mut blocks.blocks[group[0]], |label| {
+        !pred_labels.contains(label)
+    });
+    let renames = HashMap::from([(header.clone(), copied_header)]);
+    for &pred in preds {
+        if let Some(terminator) = blocks.blocks[pred].instrs.last_mut() {
+            rename_labels(terminator, &renames);
+        }
+    }
+
+    // Edges leaving the group now also come from the copy
+    for &block in group {
+        let label = blocks.blocks[block].label.clone();
+        let mut targets = blocks.succ[block].clone();
+        targets.sort();
+        targets.dedup();
+        for target in targets {
+            if group.contains(&target) {
+                continue;
+            }
+            for instr in blocks.blocks[target].instrs.iter_mut() {
+                if let Some((args, labels)) = phi_args_mut(instr) {
+                    if let Some(pos) = labels.iter().position(|l| *l == label) {
+                        args.push(args[pos].clone());
+                        labels.push(copies[&label].clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Makes the function reducible by node splitting: while the T1/T2
+/// reductions leave a cycle with several entries, one entry's group of
+/// blocks is duplicated so that each predecessor gets its own copy.
+fn function_node_splitting(blocks: bb::BasicBlocks) -> bb::BasicBlocks {
+    let mut blocks = blocks;
+    loop {
+        let (group, pred_groups) = {
+            let cfg = CFG::new(&blocks);
+            let limit = LimitGraph::new(&cfg);
+            let region = match limit.irreducible_regions().into_iter().next() {
+                Some(region) => region,
+                None => break,
+            };
+            // Duplicate as little code as possible, the entry block can't be split
+            let size = |header: usize| -> usize {
+                limit
+                    .members(header)
+                    .iter()
+                    .map(|&block| blocks.blocks[block].instrs.len())
+                    .sum()
+            };
+            let header = *region
+                .entries()
+                .iter()
+                .filter(|&&header| Some(header) != cfg.entry_node())
+                .min_by_key(|&&header| size(header))
+                .unwrap();
+            let pred_groups: Vec<Vec<usize>> = limit
+                .predecessors(header)
+                .map(|pred| {
+                    limit
+                        .members(pred)
+                        .iter()
+                        .filter(|&&block| blocks.succ[block].contains(&header))
+                        .copied()
+                        .collect()
+                })
+                .collect();
+            (limit.members(header).to_vec(), pred_groups)
+        };
+        // The first predecessor keeps the original blocks
+        for preds in &pred_groups[1..] {
+            split(&mut blocks, &group, preds);
+        }
+        blocks.recompute_edges();
+    }
+    blocks
+}
+
+pub fn node_splitting(program: &bril::Program) -> bril::Program {
+    let mut split_program = program.clone();
+    for func in &mut split_program.functions {
+        let blocks = bb::BasicBlocks::from(&func.instrs);
+        func.instrs = function_node_splitting(blocks).to_instrs();
+    }
+    split_program
+}
@@ -0,0 +1,315 @@
+use super::{bb, bril, util};
+use std::collections::{HashMap, HashSet};
+
+fn is_phi(instr: &bril::Instruction) -> bool {
+    util::is_value_op(instr, bril::ValueOps::Phi)
+}
+
+fn phis_mut(block: &mut bb::BasicBlock) -> impl Iterator<Item = &mut bril::Instruction> {
+    block.instrs.iter_mut().take_while(|instr| is_phi(instr))
+}
+
+/// Removes the phi arguments coming from `pred`.
+fn remove_phi_label(block: &mut bb::BasicBlock, pred: &str) {
+    for phi in phis_mut(block) {
+        if let bril::Instruction::Value { args, labels, .. } = phi {
+            let (new_args, new_labels) = args
+                .iter()
+                .zip(labels.iter())
+                .filter(|(_, label)| *label != pred)
+                .map(|(arg, label)| (arg.clone(), label.clone()))
+                .unzip();
+            *args = new_args;
+            *labels = new_labels;
+        }
+    }
+}
+
+fn rename_phi_label(block: &mut bb::BasicBlock, old: &str, new: &str) {
+    for phi in phis_mut(block) {
+        for label in util::get_labels_mut(phi).unwrap() {
+            if label == old {
+                *label = new.to_string();
+            }
+        }
+    }
+}
+
+fn jump_target(instr: &bril::Instruction) -> Option<&String> {
+    match instr {
+        bril::Instruction::Effect {
+            op: bril::EffectOps::Jump,
+            labels,
+            ..
+        } => labels.first(),
+        _ => None,
+    }
+}
+
+/// Looks for the value of a branch condition defined by a constant, either
+/// earlier in the same block or by the only definition in the function.
+/// Function arguments are definitions without an instruction.
+fn constant_condition(
+    blocks: &bb::BasicBlocks,
+    defs: &HashMap<&String, Vec<Option<&bril::Instruction>>>,
+    block: usize,
+    cond: &String,
+) -> Option<bool> {
+    let local = blocks.blocks[block]
+        .instrs
+        .iter()
+        .rev()
+        .find(|instr| util::get_dest(instr) == Some(cond));
+    let def = match (local, defs.get(cond).map(Vec::as_slice)) {
+        (Some(def), _) => def,
+        (None, Some([Some(def)])) => def,
+        _ => return None,
+    };
+    match def {
+        bril::Instruction::Constant {
+            value: bril::Literal::Bool(value),
+            ..
+        } => Some(*value),
+        _ => None,
+    }
+}
+
+/// Replaces branches that always go the same way by jumps.
+fn fold_branches(blocks: &mut bb::BasicBlocks, args: &[bril::Argument]) -> bool {
+    let mut defs: HashMap<_, Vec<_>> = HashMap::new();
+    for arg in args {
+        defs.entry(&arg.name).or_default().push(None);
+    }
+    for instr in blocks.blocks.iter().flat_map(|block| block.instrs.iter()) {
+        if let Some(dest) = util::get_dest(instr) {
+            defs.entry(dest).or_default().push(Some(instr));
+        }
+    }
+    let mut folds = Vec::new();
+    for (idx, block) in blocks.blocks.iter().enumerate() {
+        if let Some(bril::Instruction::Effect {
+            op: bril::EffectOps::Branch,
+            args,
+            labels,
+            ..
+        }) = block.instrs.last()
+        {
+            let taken = if labels[0] == labels[1] {
+                Some(true)
+            } else {
+                constant_condition(blocks, &defs, idx, &args[0])
+            };
+            if let Some(taken) = taken {
+                let (target, other) = if taken {
+                    (&labels[0], &labels[1])
+                } else {
+                    (&labels[1], &labels[0])
+                };
+                folds.push((idx, target.clone(), other.clone()));
+            }
+        }
+    }
+    let changed = !folds.is_empty();
+    for (idx, target, other) in folds {
+        *blocks.blocks[idx].instrs.last_mut().unwrap() = bril::Instruction::jump(target.clone());
+        if other != target {
+            let label = blocks.blocks[idx].label.clone();
+            let other = blocks.labels[&other];
+            remove_phi_label(&mut blocks.blocks[other], &label);
+        }
+    }
+    changed
+}
+
+/// Deletes blocks that can't be reached from the entry.
+fn remove_unreachable(blocks: &mut bb::BasicBlocks) -> bool {
+    let mut reachable = vec![false; blocks.blocks.len()];
+    let mut stack = vec![0];
+    while let Some(idx) = stack.pop() {
+        if !reachable[idx] {
+            reachable[idx] = true;
+            stack.extend(blocks.succ[idx].iter().copied());
+        }
+    }
+    if reachable.iter().all(|&reachable| reachable) {
+        return false;
+    }
+    for (idx, _) in reachable
+        .iter()
+        .enumerate()
+        .filter(|(_, &reachable)| !reachable)
+    {
+        let label = blocks.blocks[idx].label.clone();
+        for succ in blocks.succ[idx].clone() {
+            remove_phi_label(&mut blocks.blocks[succ], &label);
+        }
+    }
+    rebuild(blocks, |idx| reachable[idx]);
+    true
+}
+
+/// Sends jumps to blocks that only contain a jump straight to the final target.
+fn thread_jumps(blocks: &mut bb::BasicBlocks) -> bool {
+    let mut changed = false;
+    for idx in 1..blocks.blocks.len() {
+        let target = match blocks.blocks[idx].instrs.as_slice() {
+            [jump] => match jump_target(jump) {
+                Some(target) => blocks.labels[target],
+                None => continue,
+            },
+            _ => continue,
+        };
+        if target == idx || blocks.pred[idx].is_empty() {
+            continue;
+        }
+        let preds = blocks.pred[idx].clone();
+        let label = blocks.blocks[idx].label.clone();
+        let target_label = blocks.blocks[target].label.clone();
+        // Each phi argument from this block would need to come from every
+        // predecessor instead, which only works for a single new predecessor.
+        let has_phis = blocks.blocks[target].instrs.first().is_some_and(is_phi);
+        if has_phis {
+            let pred = preds[0];
+            if preds.len() != 1 || blocks.succ[pred].contains(&target) {
+                continue;
+            }
+            let pred_label = blocks.blocks[pred].label.clone();
+            rename_phi_label(&mut blocks.blocks[target], &label, &pred_label);
+        }
+        for pred in preds {
+            let terminator = blocks.blocks[pred].instrs.last_mut().unwrap();
+            for succ in util::get_labels_mut(terminator).unwrap() {
+                if *succ == label {
+                    *succ = target_label.clone();
+                }
+            }
+        }
+        // Update the edges so later blocks see the new shape
+        rebuild(blocks, |_| true);
+        changed = true;
+    }
+    changed
+}
+
+/// Appends blocks to their only predecessor, when it has no other successor.
+fn merge_blocks(blocks: &mut bb::BasicBlocks) -> bool {
+    let mut merged = vec![false; blocks.blocks.len()];
+    // Where the instructions of each block ended up
+    let mut owner: Vec<usize> = (0..blocks.blocks.len()).collect();
+    let mut changed = false;
+    for idx in 1..blocks.blocks.len() {
+        let pred = match blocks.pred[idx].as_slice() {
+            [pred] => *pred,
+            _ => continue,
+        };
+        if pred == idx || blocks.succ[pred].len() != 1 {
+            continue;
+        }
+        let pred_owner = owner[pred];
+        let label = blocks.blocks[idx].label.clone();
+        let pred_label = blocks.blocks[pred_owner].label.clone();
+        let mut instrs = std::mem::take(&mut blocks.blocks[idx].instrs);
+        // With one predecessor every phi is a copy
+        for instr in instrs.iter_mut().take_while(|instr| is_phi(instr)) {
+            let dest = util::unwrap_dest(instr).clone();
+            let op_type = util::unwrap_type(instr);
+            let arg = instr.args()[0].clone();
+            *instr = bril::Instruction::id(op_type, dest, arg);
+        }
+        for &succ in &blocks.succ[idx] {
+            let succ = owner[succ];
+            rename_phi_label(&mut blocks.blocks[succ], &label, &pred_label);
+        }
+        let pred_instrs = &mut blocks.blocks[pred_owner].instrs;
+        pred_instrs.pop();
+        pred_instrs.extend(instrs);
+        merged[idx] = true;
+        owner[idx] = pred_owner;
+        changed = true;
+    }
+    if changed {
+        rebuild(blocks, |idx| !merged[idx]);
+    }
+    changed
+}
+
+fn rebuild(blocks: &mut bb::BasicBlocks, keep: impl Fn(usize) -> bool) {
+    let remaining = std::mem::take(&mut blocks.blocks)
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| keep(*idx))
+        .map(|(_, block)| block)
+        .collect();
+    *blocks = bb::BasicBlocks::from_blocks(remaining);
+}
+
+fn function_simplify_cfg(blocks: &mut bb::BasicBlocks, args: &[bril::Argument]) {
+    if blocks.blocks.is_empty() {
+        return;
+    }
+    loop {
+        let mut changed = fold_branches(blocks, args);
+        if changed {
+            rebuild(blocks, |_| true);
+        }
+        changed |= remove_unreachable(blocks);
+        changed |= thread_jumps(blocks);
+        changed |= merge_blocks(blocks);
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Turns the blocks back into code, leaving out jumps to the next block and
+/// labels that nothing refers to.
+fn to_code(blocks: bb::BasicBlocks) -> Vec<bril::Code> {
+    let next_labels: Vec<_> = blocks
+        .blocks
+        .iter()
+        .skip(1)
+        .map(|block| Some(block.label.clone()))
+        .chain(std::iter::once(None))
+        .collect();
+    let mut instrs = Vec::new();
+    for (block, next) in blocks.blocks.into_iter().zip(next_labels) {
+        instrs.push(bril::Code::Label { label: block.label });
+        let mut block_instrs = block.instrs;
+        let falls_through = match block_instrs.last() {
+            Some(last) => jump_target(last).is_some() && jump_target(last) == next.as_ref(),
+            None => false,
+        };
+        let returns_at_end = next.is_none()
+            && block_instrs
+                .last()
+                .is_some_and(|last| last.is_return() && last.args().is_empty());
+        if falls_through || returns_at_end {
+            block_instrs.pop();
+        }
+        instrs.extend(block_instrs.into_iter().map(bril::Code::Instruction));
+    }
+    let used: HashSet<String> = instrs
+        .iter()
+        .filter_map(util::get_instr)
+        .filter_map(util::get_labels)
+        .flatten()
+        .cloned()
+        .collect();
+    instrs
+        .into_iter()
+        .filter(|code| match code {
+            bril::Code::Label { label } => used.contains(label),
+            _ => true,
+        })
+        .collect()
+}
+
+pub fn simplify_cfg(program: &bril::Program) -> bril::Program {
+    let mut simplified = program.clone();
+    for func in &mut simplified.functions {
+        let mut blocks = bb::BasicBlocks::from(&func.instrs);
+        function_simplify_cfg(&mut blocks, &func.args);
+        func.instrs = to_code(blocks);
+    }
+    simplified
+}
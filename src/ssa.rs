@@ -1,75 +1,13 @@
-use super::{bb, bril, cfg, util};
-use itertools::{izip, Itertools};
-use ordered_float::OrderedFloat;
+use super::{analysis, bb, bril, cfg, util};
+use itertools::izip;
+use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 
 pub struct SSA {
     pub function: bril::Function,
 }
 
-fn type_name(var_type: &bril::Type) -> String {
-    match var_type {
-        bril::Type::Bool => "bool".to_string(),
-        bril::Type::Int => "int".to_string(),
-        bril::Type::Float => "float".to_string(),
-        bril::Type::Pointer(ptr_type) => format!("ptr.{}", type_name(ptr_type)),
-    }
-}
-
-fn undefined_var(var_type: &bril::Type) -> String {
-    format!("__undefined.{}", type_name(var_type))
-}
-
-fn undefined_value(var_type: &bril::Type) -> bril::Literal {
-    match var_type {
-        bril::Type::Bool => bril::Literal::Bool(false),
-        bril::Type::Int => bril::Literal::Int(0),
-        bril::Type::Float => bril::Literal::Float(OrderedFloat(0.0)),
-        bril::Type::Pointer(_) => panic!("Unsupported"),
-    }
-}
-
-fn add_undefined_vars(blocks: &mut bb::BasicBlocks, types: &HashMap<String, bril::Type>) {
-    let mut types: Vec<_> = types.values().cloned().collect();
-    types.sort();
-    let types: Vec<_> = types.into_iter().unique().collect();
-    let mut instrs = Vec::new();
-    if types.iter().any(|t| matches!(t, bril::Type::Pointer(_))) {
-        instrs.push(bril::Instruction::const_int(
-            "__undefined.zero".to_string(),
-            0,
-        ));
-    }
-    for undef_type in types {
-        let dest = undefined_var(&undef_type);
-        let instr = match undef_type {
-            bril::Type::Pointer(ptr_type) => {
-                bril::Instruction::alloc(dest, "__undefined.zero".to_string(), *ptr_type)
-            }
-            undef_type => {
-                let value = undefined_value(&undef_type);
-                bril::Instruction::constant(undef_type, dest, value)
-            }
-        };
-        instrs.push(instr);
-    }
-    // if let Some(block) = block {
-    //     block.instrs.insert(
-    //         0,
-    //         bril::Instruction::const_int(undefined_var(&bril::Type::Int), 0),
-    //     );
-    //     block.instrs.insert(
-    //         0,
-    //         bril::Instruction::const_bool(undefined_var(&bril::Type::Bool), false),
-    //     );
-    // }
-    let block = blocks.blocks.first_mut();
-    if let Some(block) = block {
-        std::mem::swap(&mut instrs, &mut block.instrs);
-        block.instrs.extend(instrs.into_iter());
-    }
-}
-
 #[derive(Clone)]
 struct Def {
     block: usize,
@@ -80,13 +18,11 @@ struct ReachingDefs<'a> {
     dom: &'a cfg::DominanceTree<'a>,
     counts: HashMap<String, usize>,
     defs: HashMap<String, Def>,
-    types: &'a HashMap<String, bril::Type>,
 }
 
 impl<'a> ReachingDefs<'a> {
     fn new<'b>(
         dom: &'a cfg::DominanceTree<'a>,
-        types: &'a HashMap<String, bril::Type>,
         args: impl Iterator<Item = &'b String>,
     ) -> ReachingDefs<'a> {
         let defs = args
@@ -104,7 +40,6 @@ impl<'a> ReachingDefs<'a> {
             dom,
             defs,
             counts: HashMap::new(),
-            types,
         }
     }
 
@@ -134,8 +69,7 @@ impl<'a> ReachingDefs<'a> {
         let mut r = self.reaching_def(var);
         loop {
             if let Some(def) = &r {
-                let dominates = |x, y| self.dom.dominated(x).any(|b| b.idx() == y);
-                if !dominates(def.block, block) {
+                if !self.dom.dominates(def.block, block) {
                     r = self.reaching_def(&def.name);
                     continue;
                 }
@@ -151,16 +85,15 @@ impl<'a> ReachingDefs<'a> {
 
     fn rename_args(&mut self, block: usize, args: &mut Vec<String>) {
         for arg in args {
-            *arg = self.new_arg(block, arg);
+            // Without any definition reaching it, the use is always undefined
+            if let Some(new_arg) = self.new_arg(block, arg) {
+                *arg = new_arg;
+            }
         }
     }
 
-    fn new_arg(&mut self, block: usize, arg: &String) -> String {
-        let def = self.update_reaching_def(arg, block);
-        match def {
-            Some(def) => def.name,
-            None => undefined_var(&self.types[arg]),
-        }
+    fn new_arg(&mut self, block: usize, arg: &String) -> Option<String> {
+        self.update_reaching_def(arg, block).map(|def| def.name)
     }
 
     fn rename_dest(&mut self, block: usize, dest: &mut String) {
@@ -173,7 +106,6 @@ fn rename<'a, 'b>(
     phis: &Vec<BTreeSet<String>>,
     cfg: &cfg::CFG,
     dom: &cfg::DominanceTree,
-    types: &HashMap<String, bril::Type>,
     args: impl Iterator<Item = &'a String>,
 ) -> (
     Vec<BTreeMap<String, (String, Vec<(String, String)>)>>,
@@ -188,14 +120,29 @@ fn rename<'a, 'b>(
                 .collect()
         })
         .collect();
-    let mut reaching_defs = ReachingDefs::new(dom, types, args);
+    let mut reaching_defs = ReachingDefs::new(dom, args);
+    let mut phi_args = Vec::new();
     rename_impl(
         &mut blocks,
         &mut reaching_defs,
         &mut out_phis,
+        &mut phi_args,
         dom,
         cfg.get_block(0),
     );
+    // Remove undefined arguments from the back, so the indices stay valid
+    phi_args.sort_by_key(|&(block, instr, arg, _)| (block, instr, std::cmp::Reverse(arg)));
+    for (block, instr, arg, name) in phi_args {
+        if let bril::Instruction::Value { args, labels, .. } = &mut blocks[block][instr] {
+            match name {
+                Some(name) => args[arg] = name,
+                None => {
+                    args.remove(arg);
+                    labels.remove(arg);
+                }
+            }
+        }
+    }
     (out_phis, blocks)
 }
 
@@ -203,6 +150,8 @@ fn rename_impl(
     blocks: &mut Vec<Vec<bril::Instruction>>,
     reaching_defs: &mut ReachingDefs,
     phis: &mut Vec<BTreeMap<String, (String, Vec<(String, String)>)>>,
+    // Renamed arguments of phis already in the input: (block, instr, arg, name)
+    phi_args: &mut Vec<(usize, usize, usize, Option<String>)>,
     dom: &cfg::DominanceTree,
     block: cfg::Block,
 ) {
@@ -220,12 +169,15 @@ fn rename_impl(
                 reaching_defs.rename_dest(block.idx(), dest);
             }
             &mut bril::Instruction::Value {
+                op,
                 ref mut dest,
                 ref mut args,
                 ..
             } => {
-                // assert_ne!(op, bril::ValueOps::Phi);
-                reaching_defs.rename_args(block.idx(), args);
+                // Phi arguments are renamed at the end of their predecessor
+                if op != bril::ValueOps::Phi {
+                    reaching_defs.rename_args(block.idx(), args);
+                }
                 reaching_defs.rename_dest(block.idx(), dest);
             }
             &mut bril::Instruction::Effect { ref mut args, .. } => {
@@ -236,9 +188,25 @@ fn rename_impl(
     }
 
     for &idx in block.succ() {
+        // A phi leaves out the edges its variable is undefined along
         for (var, (_dest, args)) in phis[idx].iter_mut() {
-            let name = reaching_defs.new_arg(block.idx(), var);
-            args.push((block.label().clone(), name));
+            if let Some(name) = reaching_defs.new_arg(block.idx(), var) {
+                args.push((block.label().clone(), name));
+            }
+        }
+    }
+    for succ in block.successors() {
+        for (instr_idx, instr) in succ.instrs().iter().enumerate() {
+            if !util::is_value_op(instr, bril::ValueOps::Phi) {
+                continue;
+            }
+            let labels = util::get_labels(instr).unwrap();
+            for (arg_idx, (label, arg)) in labels.iter().zip(instr.args()).enumerate() {
+                if label == block.label() {
+                    let name = reaching_defs.new_arg(block.idx(), arg);
+                    phi_args.push((succ.idx(), instr_idx, arg_idx, name));
+                }
+            }
         }
     }
     eprintln!(
@@ -247,7 +215,7 @@ fn rename_impl(
         dom.immediately_dominated(block.idx()).collect::<Vec<_>>()
     );
     for block in dom.immediately_dominated(block.idx()) {
-        rename_impl(blocks, reaching_defs, phis, dom, block);
+        rename_impl(blocks, reaching_defs, phis, phi_args, dom, block);
     }
 }
 
@@ -263,8 +231,905 @@ fn phi(dest: String, op_type: bril::Type, labels_args: Vec<(String, String)>) ->
     }
 }
 
+struct Phi {
+    dest: String,
+    op_type: bril::Type,
+    /// (label of the predecessor, argument)
+    args: Vec<(String, String)>,
+}
+
+fn get_args_mut(instr: &mut bril::Instruction) -> Option<&mut Vec<String>> {
+    match instr {
+        bril::Instruction::Constant { .. } => None,
+        bril::Instruction::Value { args, .. } => Some(args),
+        bril::Instruction::Effect { args, .. } => Some(args),
+    }
+}
+
+fn remove_phis(blocks: &mut bb::BasicBlocks) -> Vec<Vec<Phi>> {
+    let mut phis = Vec::new();
+    for block in &mut blocks.blocks {
+        let (block_phis, instrs): (Vec<_>, Vec<_>) = std::mem::take(&mut block.instrs)
+            .into_iter()
+            .partition(|instr| util::is_value_op(instr, bril::ValueOps::Phi));
+        block.instrs = instrs;
+        phis.push(
+            block_phis
+                .into_iter()
+                .map(|instr| Phi {
+                    dest: util::unwrap_dest(&instr).clone(),
+                    op_type: util::unwrap_type(&instr),
+                    args: util::get_labels(&instr)
+                        .unwrap()
+                        .iter()
+                        .cloned()
+                        .zip(instr.args().iter().cloned())
+                        .collect(),
+                })
+                .collect(),
+        );
+    }
+    phis
+}
+
+/**
+ * Liveness where phis define their dests at the start of their block and
+ * use their arguments at the end of the corresponding predecessor.
+ */
+fn ssa_liveness(
+    blocks: &bb::BasicBlocks,
+    phis: &[Vec<Phi>],
+) -> (Vec<HashSet<String>>, Vec<HashSet<String>>) {
+    let len = blocks.blocks.len();
+    let mut live_in: Vec<HashSet<String>> = vec![HashSet::new(); len];
+    let mut live_out: Vec<HashSet<String>> = vec![HashSet::new(); len];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..len).rev() {
+            let label = &blocks.blocks[idx].label;
+            let mut out = HashSet::new();
+            for &succ in &blocks.succ[idx] {
+                let phi_dests: HashSet<_> = phis[succ].iter().map(|phi| &phi.dest).collect();
+                out.extend(
+                    live_in[succ]
+                        .iter()
+                        .filter(|var| !phi_dests.contains(var))
+                        .cloned(),
+                );
+                for phi in &phis[succ] {
+                    out.extend(
+                        phi.args
+                            .iter()
+                            .filter(|(pred, _)| pred == label)
+                            .map(|(_, arg)| arg.clone()),
+                    );
+                }
+            }
+            let mut live = out.clone();
+            for instr in blocks.blocks[idx].instrs.iter().rev() {
+                if let Some(dest) = util::get_dest(instr) {
+                    live.remove(dest);
+                }
+                live.extend(instr.args().iter().cloned());
+            }
+            live.extend(phis[idx].iter().map(|phi| phi.dest.clone()));
+            if live != live_in[idx] || out != live_out[idx] {
+                live_in[idx] = live;
+                live_out[idx] = out;
+                changed = true;
+            }
+        }
+    }
+    (live_in, live_out)
+}
+
+struct Interference {
+    edges: HashSet<(String, String)>,
+}
+
+impl Interference {
+    fn new(blocks: &bb::BasicBlocks, phis: &[Vec<Phi>], live_out: &[HashSet<String>]) -> Self {
+        let mut interference = Interference {
+            edges: HashSet::new(),
+        };
+        for (idx, block) in blocks.blocks.iter().enumerate() {
+            let mut live = live_out[idx].clone();
+            for instr in block.instrs.iter().rev() {
+                if let Some(dest) = util::get_dest(instr) {
+                    live.remove(dest);
+                    for var in &live {
+                        interference.add(dest, var);
+                    }
+                }
+                live.extend(instr.args().iter().cloned());
+            }
+            // Phi dests are all defined at once, at the start of the block
+            for phi in &phis[idx] {
+                live.remove(&phi.dest);
+            }
+            for phi in &phis[idx] {
+                for var in live.iter().chain(phis[idx].iter().map(|phi| &phi.dest)) {
+                    if var != &phi.dest {
+                        interference.add(&phi.dest, var);
+                    }
+                }
+            }
+        }
+        interference
+    }
+
+    fn add(&mut self, x: &str, y: &str) {
+        self.edges.insert((x.to_string(), y.to_string()));
+        self.edges.insert((y.to_string(), x.to_string()));
+    }
+
+    fn interferes(&self, x: &str, y: &str) -> bool {
+        self.edges.contains(&(x.to_string(), y.to_string()))
+    }
+}
+
+/**
+ * Union-find over variables that will share a name. Function arguments keep
+ * their names, so two arguments are never put in the same web.
+ */
+struct Webs {
+    parent: HashMap<String, String>,
+    members: HashMap<String, Vec<String>>,
+    args: HashSet<String>,
+}
+
+impl Webs {
+    fn new<'a>(args: impl Iterator<Item = &'a String>) -> Self {
+        Webs {
+            parent: HashMap::new(),
+            members: HashMap::new(),
+            args: args.cloned().collect(),
+        }
+    }
+
+    fn find(&self, var: &String) -> String {
+        let mut var = var;
+        while let Some(parent) = self.parent.get(var) {
+            var = parent;
+        }
+        var.clone()
+    }
+
+    fn members(&self, root: &String) -> Vec<String> {
+        self.members
+            .get(root)
+            .cloned()
+            .unwrap_or_else(|| vec![root.clone()])
+    }
+
+    fn coalesce(&mut self, x: &String, y: &String, interference: &Interference) {
+        let (x, y) = (self.find(x), self.find(y));
+        if x == y || (self.args.contains(&x) && self.args.contains(&y)) {
+            return;
+        }
+        let (x_members, y_members) = (self.members(&x), self.members(&y));
+        let interferes = x_members
+            .iter()
+            .any(|x| y_members.iter().any(|y| interference.interferes(x, y)));
+        if interferes {
+            return;
+        }
+        // Keep argument names as the root
+        let (root, child) = if self.args.contains(&y) {
+            (y, x)
+        } else {
+            (x, y)
+        };
+        self.members.remove(&child);
+        self.members.insert(
+            root.clone(),
+            x_members.into_iter().chain(y_members).collect(),
+        );
+        self.parent.insert(child, root);
+    }
+}
+
+/// Copies of (type, dest, src) that all happen at once
+type ParallelCopy = Vec<(bril::Type, String, String)>;
+
+/**
+ * Orders a parallel copy so that no destination is written before every copy
+ * reading it has happened. Cycles are broken by saving one destination in a
+ * temporary first.
+ */
+fn sequentialize(
+    mut copies: ParallelCopy,
+    fresh: &mut impl FnMut() -> String,
+) -> Vec<bril::Instruction> {
+    let mut instrs = Vec::new();
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|(_, dest, _)| !copies.iter().any(|(_, _, src)| src == dest));
+        match ready {
+            Some(idx) => {
+                let (op_type, dest, src) = copies.remove(idx);
+                instrs.push(bril::Instruction::id(op_type, dest, src));
+            }
+            None => {
+                let (op_type, dest, _) = copies[0].clone();
+                let temp = fresh();
+                instrs.push(bril::Instruction::id(op_type, temp.clone(), dest.clone()));
+                for (_, _, src) in &mut copies {
+                    if *src == dest {
+                        *src = temp.clone();
+                    }
+                }
+            }
+        }
+    }
+    instrs
+}
+
+fn get(dest: String, op_type: bril::Type) -> bril::Instruction {
+    bril::Instruction::Value {
+        dest,
+        op_type,
+        op: bril::ValueOps::Get,
+        args: Vec::new(),
+        funcs: Vec::new(),
+        labels: Vec::new(),
+    }
+}
+
+fn set(shadow: String, arg: String) -> bril::Instruction {
+    bril::Instruction::Effect {
+        op: bril::EffectOps::Set,
+        args: vec![shadow, arg],
+        funcs: Vec::new(),
+        labels: Vec::new(),
+    }
+}
+
+fn undef(dest: String, op_type: bril::Type) -> bril::Instruction {
+    bril::Instruction::Value {
+        dest,
+        op_type,
+        op: bril::ValueOps::Undef,
+        args: Vec::new(),
+        funcs: Vec::new(),
+        labels: Vec::new(),
+    }
+}
+
+fn insert_before_terminator(
+    block: &mut bb::BasicBlock,
+    instrs: impl IntoIterator<Item = bril::Instruction>,
+) {
+    let end = block.instrs.len() - 1;
+    block.instrs.splice(end..end, instrs);
+}
+
+/**
+ * Rewrites phis as `get`s of a shadow variable named after their dest, with
+ * every predecessor `set`ting the shadow right before its terminator. A
+ * predecessor the phi has no argument for sets it to a fresh `undef`.
+ */
+fn phis_to_set_get(function: &bril::Function) -> bril::Function {
+    let mut blocks = bb::BasicBlocks::from(&function.instrs);
+    let phis = remove_phis(&mut blocks);
+    let prefix = "_".repeat(util::max_underscores(function) + 1);
+    let mut undefs = 0;
+    let mut sets = vec![Vec::new(); blocks.blocks.len()];
+    for (idx, phis) in phis.into_iter().enumerate() {
+        let mut preds = blocks.pred[idx].clone();
+        preds.sort_unstable();
+        preds.dedup();
+        for phi in &phis {
+            for &pred in &preds {
+                let label = &blocks.blocks[pred].label;
+                let arg = match phi.args.iter().find(|(arg_label, _)| arg_label == label) {
+                    Some((_, arg)) => arg.clone(),
+                    None => {
+                        let name = format!("{}undef{}", prefix, undefs);
+                        undefs += 1;
+                        sets[pred].push(undef(name.clone(), phi.op_type.clone()));
+                        name
+                    }
+                };
+                sets[pred].push(set(phi.dest.clone(), arg));
+            }
+        }
+        let gets = phis.into_iter().map(|phi| get(phi.dest, phi.op_type));
+        blocks.blocks[idx].instrs.splice(0..0, gets);
+    }
+    for (block, sets) in blocks.blocks.iter_mut().zip(sets) {
+        insert_before_terminator(block, sets);
+    }
+    bril::Function {
+        instrs: blocks.to_instrs(),
+        ..function.clone()
+    }
+}
+
+/// What a shadow variable holds at some point of a `set`/`get` program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Shadow {
+    /// Not worked out yet
+    Unknown,
+    /// Nothing set along the way, or only an `undef`
+    Undefined,
+    Value(String),
+    /// Different values along different paths
+    Conflict,
+}
+
+impl Shadow {
+    fn meet(self, other: &Shadow) -> Shadow {
+        match (self, other) {
+            (Shadow::Unknown, other) => other.clone(),
+            (shadow, Shadow::Unknown) => shadow,
+            (Shadow::Undefined, Shadow::Undefined) => Shadow::Undefined,
+            (Shadow::Value(a), Shadow::Value(b)) if a == *b => Shadow::Value(a),
+            _ => Shadow::Conflict,
+        }
+    }
+}
+
+/**
+ * What `shadow` holds at the start and at the end of each block. Blocks with
+ * a head start with the variable named there, the others with whatever their
+ * predecessors agree on.
+ */
+fn shadow_values(
+    blocks: &bb::BasicBlocks,
+    sets: &[HashMap<String, String>],
+    undefs: &HashSet<String>,
+    shadow: &str,
+    heads: &[Option<String>],
+) -> (Vec<Shadow>, Vec<Shadow>) {
+    let mut starts = vec![Shadow::Unknown; blocks.blocks.len()];
+    let mut ends = vec![Shadow::Unknown; blocks.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..blocks.blocks.len() {
+            starts[idx] = match blocks.pred[idx].as_slice() {
+                [] => Shadow::Undefined,
+                preds => preds
+                    .iter()
+                    .fold(Shadow::Unknown, |start, &pred| start.meet(&ends[pred])),
+            };
+            let end = match (sets[idx].get(shadow), &heads[idx]) {
+                (Some(arg), _) if undefs.contains(arg) => Shadow::Undefined,
+                (Some(arg), _) => Shadow::Value(arg.clone()),
+                (None, Some(head)) => Shadow::Value(head.clone()),
+                (None, None) => starts[idx].clone(),
+            };
+            if end != ends[idx] {
+                ends[idx] = end;
+                changed = true;
+            }
+        }
+    }
+    (starts, ends)
+}
+
+/**
+ * Rewrites `get`s as phis. The argument along each edge is the value the
+ * shadow holds at the end of the predecessor, from the last `set` on the way
+ * there. Where different values meet in a block without a `get`, that block
+ * gets a phi of its own, which is dropped again if nothing reads it.
+ * Arguments that are `undef` are left out, and so is the `undef` once
+ * nothing else uses it.
+ */
+fn set_get_to_phis(function: &bril::Function) -> bril::Function {
+    let mut blocks = bb::BasicBlocks::from(&function.instrs);
+    let undefs: HashSet<String> = blocks
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter())
+        .filter(|instr| util::is_value_op(instr, bril::ValueOps::Undef))
+        .map(|instr| util::unwrap_dest(instr).clone())
+        .collect();
+    let mut sets: Vec<HashMap<String, String>> = Vec::new();
+    for block in &mut blocks.blocks {
+        let mut block_sets = HashMap::new();
+        block.instrs.retain(|instr| match instr {
+            bril::Instruction::Effect {
+                op: bril::EffectOps::Set,
+                args,
+                ..
+            } => {
+                block_sets.insert(args[0].clone(), args[1].clone());
+                false
+            }
+            _ => true,
+        });
+        sets.push(block_sets);
+    }
+    let mut shadows = Vec::new();
+    for instr in blocks.blocks.iter().flat_map(|block| block.instrs.iter()) {
+        if util::is_value_op(instr, bril::ValueOps::Get) {
+            let dest = util::unwrap_dest(instr);
+            if !shadows.iter().any(|(shadow, _)| shadow == dest) {
+                shadows.push((dest.clone(), util::unwrap_type(instr)));
+            }
+        }
+    }
+    let mut names: HashSet<String> = blocks
+        .get_referenced_variables()
+        .chain(function.args.iter().map(|arg| &arg.name))
+        .cloned()
+        .collect();
+    // The phis added where values meet, with their arguments
+    let mut inserted: HashMap<String, Vec<String>> = HashMap::new();
+
+    let num_blocks = blocks.blocks.len();
+    for (shadow, op_type) in shadows {
+        let is_get = |instr: &bril::Instruction| {
+            util::is_value_op(instr, bril::ValueOps::Get) && *util::unwrap_dest(instr) == shadow
+        };
+        // The variable each block starts with, if it doesn't just take the
+        // shadow from its predecessors
+        let mut heads: Vec<_> = blocks
+            .blocks
+            .iter()
+            .map(|block| block.instrs.iter().any(&is_get).then(|| shadow.clone()))
+            .collect();
+        let ends = loop {
+            let (starts, ends) = shadow_values(&blocks, &sets, &undefs, &shadow, &heads);
+            let conflicts: Vec<_> = (0..num_blocks)
+                .filter(|&idx| heads[idx].is_none() && starts[idx] == Shadow::Conflict)
+                .collect();
+            if conflicts.is_empty() {
+                break ends;
+            }
+            for idx in conflicts {
+                let name = (0..)
+                    .map(|n| format!("{}.{}", shadow, n))
+                    .find(|name| !names.contains(name))
+                    .unwrap();
+                names.insert(name.clone());
+                heads[idx] = Some(name);
+            }
+        };
+        for (idx, head) in heads.into_iter().enumerate() {
+            let head = match head {
+                Some(head) => head,
+                None => continue,
+            };
+            let mut preds = blocks.pred[idx].clone();
+            preds.sort_unstable();
+            preds.dedup();
+            let args: Vec<(String, String)> = preds
+                .iter()
+                .filter_map(|&pred| match &ends[pred] {
+                    Shadow::Value(arg) => Some((blocks.blocks[pred].label.clone(), arg.clone())),
+                    _ => None,
+                })
+                .collect();
+            let instrs = &mut blocks.blocks[idx].instrs;
+            match instrs.iter().position(&is_get) {
+                Some(pos) => instrs[pos] = phi(head, op_type.clone(), args),
+                None => {
+                    let vars = args.iter().map(|(_, arg)| arg.clone()).collect();
+                    instrs.insert(0, phi(head.clone(), op_type.clone(), args));
+                    inserted.insert(head, vars);
+                }
+            }
+        }
+    }
+
+    // The added phis that something other than themselves depends on
+    let mut needed: HashSet<String> = blocks
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter())
+        .filter(|instr| util::get_dest(instr).is_none_or(|dest| !inserted.contains_key(dest)))
+        .flat_map(|instr| instr.args().iter().cloned())
+        .collect();
+    let mut worklist: Vec<_> = needed
+        .iter()
+        .filter(|var| inserted.contains_key(*var))
+        .cloned()
+        .collect();
+    while let Some(var) = worklist.pop() {
+        for arg in &inserted[&var] {
+            if inserted.contains_key(arg) && needed.insert(arg.clone()) {
+                worklist.push(arg.clone());
+            }
+        }
+    }
+    for block in &mut blocks.blocks {
+        block.instrs.retain(|instr| {
+            util::get_dest(instr)
+                .is_none_or(|dest| !inserted.contains_key(dest) || needed.contains(dest))
+        });
+    }
+
+    let used: HashSet<String> = blocks
+        .blocks
+        .iter()
+        .flat_map(|block| block.instrs.iter())
+        .flat_map(|instr| instr.args().iter().cloned())
+        .collect();
+    for block in &mut blocks.blocks {
+        block.instrs.retain(|instr| {
+            !util::is_value_op(instr, bril::ValueOps::Undef)
+                || used.contains(util::unwrap_dest(instr))
+        });
+    }
+    bril::Function {
+        instrs: blocks.to_instrs(),
+        ..function.clone()
+    }
+}
+
+/// A way a function fails to be in SSA form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SSAError {
+    MultipleDefinitions {
+        var: String,
+    },
+    UndefinedUse {
+        var: String,
+        block: String,
+    },
+    UndominatedUse {
+        var: String,
+        block: String,
+    },
+    PhiLabelNotPredecessor {
+        phi: String,
+        block: String,
+        label: String,
+    },
+    PhiDuplicateLabel {
+        phi: String,
+        block: String,
+        label: String,
+    },
+    PhiNotAtStart {
+        phi: String,
+        block: String,
+    },
+}
+
+impl fmt::Display for SSAError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SSAError::MultipleDefinitions { var } => write!(f, "{} is defined more than once", var),
+            SSAError::UndefinedUse { var, block } => {
+                write!(f, "{} is used in .{} but never defined", var, block)
+            }
+            SSAError::UndominatedUse { var, block } => write!(
+                f,
+                "use of {} in .{} is not dominated by its definition",
+                var, block
+            ),
+            SSAError::PhiLabelNotPredecessor { phi, block, label } => write!(
+                f,
+                "phi {} in .{} has an argument for .{}, which is not a predecessor",
+                phi, block, label
+            ),
+            SSAError::PhiDuplicateLabel { phi, block, label } => write!(
+                f,
+                "phi {} in .{} has more than one argument for .{}",
+                phi, block, label
+            ),
+            SSAError::PhiNotAtStart { phi, block } => write!(
+                f,
+                "phi {} in .{} comes after an instruction that is not a phi",
+                phi, block
+            ),
+        }
+    }
+}
+
+/**
+ * Checks the SSA invariants: single definitions, definitions dominating their
+ * uses, with phi arguments used at the end of the matching predecessor, phi
+ * labels naming distinct predecessors and phis only at the start of blocks.
+ * A phi may leave out predecessors it is undefined along. In the `set`/`get`
+ * form a `get` is a definition and a `set` only uses the value it sets.
+ */
+fn verify(function: &bril::Function) -> Vec<SSAError> {
+    let blocks = bb::BasicBlocks::from(&function.instrs);
+    let cfg = cfg::CFG::new(&blocks);
+    let dom = cfg::DominanceTree::new(cfg);
+    let mut errors = Vec::new();
+
+    // Where each variable is defined, as (block, position in the block)
+    let mut defs: HashMap<&String, (usize, Option<usize>)> = HashMap::new();
+    let all_defs = function.args.iter().map(|arg| (&arg.name, 0, None)).chain(
+        blocks.blocks.iter().enumerate().flat_map(|(idx, block)| {
+            block
+                .instrs
+                .iter()
+                .enumerate()
+                .filter_map(move |(pos, instr)| {
+                    util::get_dest(instr).map(|dest| (dest, idx, Some(pos)))
+                })
+        }),
+    );
+    for (var, idx, pos) in all_defs {
+        match defs.entry(var) {
+            Entry::Occupied(_) => {
+                errors.push(SSAError::MultipleDefinitions { var: var.clone() });
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((idx, pos));
+            }
+        }
+    }
+
+    for (idx, block) in blocks.blocks.iter().enumerate() {
+        let label = &block.label;
+        let check_use = |var: &String, at: usize, pos: Option<usize>, errors: &mut Vec<_>| {
+            match defs.get(var) {
+                None => errors.push(SSAError::UndefinedUse {
+                    var: var.clone(),
+                    block: label.clone(),
+                }),
+                Some(&(def_block, def_pos)) => {
+                    let dominates = if def_block == at {
+                        def_pos < pos || pos.is_none()
+                    } else {
+                        dom.dominates(def_block, at)
+                    };
+                    if !dominates {
+                        errors.push(SSAError::UndominatedUse {
+                            var: var.clone(),
+                            block: label.clone(),
+                        });
+                    }
+                }
+            }
+        };
+        let mut in_phis = true;
+        for (pos, instr) in block.instrs.iter().enumerate() {
+            if !util::is_value_op(instr, bril::ValueOps::Phi) {
+                in_phis = false;
+                // The shadow variable a `set` writes to isn't a use
+                let args = match instr {
+                    bril::Instruction::Effect {
+                        op: bril::EffectOps::Set,
+                        args,
+                        ..
+                    } => args.get(1..).unwrap_or(&[]),
+                    _ => instr.args(),
+                };
+                for arg in args {
+                    check_use(arg, idx, Some(pos), &mut errors);
+                }
+                continue;
+            }
+            let phi = util::unwrap_dest(instr);
+            if !in_phis {
+                errors.push(SSAError::PhiNotAtStart {
+                    phi: phi.clone(),
+                    block: label.clone(),
+                });
+            }
+            let mut seen = HashSet::new();
+            for (arg, arg_label) in instr.args().iter().zip(util::get_labels(instr).unwrap()) {
+                let pred = blocks
+                    .labels
+                    .get(arg_label)
+                    .filter(|pred| blocks.pred[idx].contains(pred));
+                let pred = match pred {
+                    Some(&pred) => pred,
+                    None => {
+                        errors.push(SSAError::PhiLabelNotPredecessor {
+                            phi: phi.clone(),
+                            block: label.clone(),
+                            label: arg_label.clone(),
+                        });
+                        continue;
+                    }
+                };
+                if !seen.insert(arg_label) {
+                    errors.push(SSAError::PhiDuplicateLabel {
+                        phi: phi.clone(),
+                        block: label.clone(),
+                        label: arg_label.clone(),
+                    });
+                }
+                // The argument is read at the end of the predecessor
+                check_use(arg, pred, None, &mut errors);
+            }
+        }
+    }
+    errors
+}
+
+/**
+ * Restores single assignment for variables that a transform gave several
+ * definitions, e.g. by copying blocks and keeping their names. Each
+ * definition gets a fresh name, phis go where the definitions meet and the
+ * variable is live, and every use is pointed at the definition reaching it.
+ * The entry block must not have predecessors, as with `BasicBlocks::from`.
+ */
+pub struct SSAUpdater<'a> {
+    blocks: &'a mut bb::BasicBlocks,
+    args: &'a [bril::Argument],
+    children: Vec<Vec<usize>>,
+    frontiers: Vec<Vec<usize>>,
+    live_in: Vec<HashSet<String>>,
+    names: HashSet<String>,
+}
+
+impl<'a> SSAUpdater<'a> {
+    pub fn new(blocks: &'a mut bb::BasicBlocks, args: &'a [bril::Argument]) -> Self {
+        let (children, frontiers) = {
+            let cfg = cfg::CFG::new(blocks);
+            let dom = cfg::DominanceTree::new(cfg);
+            (0..cfg.len())
+                .map(|idx| {
+                    let children = dom.immediately_dominated(idx).map(|b| b.idx()).collect();
+                    let mut frontier: Vec<_> =
+                        dom.dominance_frontier(idx).map(|(_, b)| b.idx()).collect();
+                    frontier.sort_unstable();
+                    frontier.dedup();
+                    (children, frontier)
+                })
+                .unzip()
+        };
+        let (_, live_in) = analysis::live_variables(blocks);
+        let names = blocks
+            .get_referenced_variables()
+            .chain(args.iter().map(|arg| &arg.name))
+            .cloned()
+            .collect();
+        SSAUpdater {
+            blocks,
+            args,
+            children,
+            frontiers,
+            live_in,
+            names,
+        }
+    }
+
+    fn fresh(&mut self, var: &str) -> String {
+        let name = (0..)
+            .map(|n| format!("{}.{}", var, n))
+            .find(|name| !self.names.contains(name))
+            .unwrap();
+        self.names.insert(name.clone());
+        name
+    }
+
+    /**
+     * Renames the definitions of `var` apart and rewrites its uses.
+     */
+    pub fn update(&mut self, var: &str) {
+        let arg = self.args.iter().find(|arg| arg.name == var);
+        let mut op_type = arg.map(|arg| arg.arg_type.clone());
+        let mut def_blocks = Vec::new();
+        if arg.is_some() {
+            def_blocks.push(0);
+        }
+        for (idx, block) in self.blocks.blocks.iter().enumerate() {
+            for instr in &block.instrs {
+                if util::get_dest(instr).is_some_and(|dest| dest == var) {
+                    op_type.get_or_insert_with(|| util::unwrap_type(instr));
+                    def_blocks.push(idx);
+                }
+            }
+        }
+        let op_type = match op_type {
+            Some(op_type) => op_type,
+            None => return,
+        };
+
+        // The name of the phi inserted for `var` in each block
+        let mut phis: Vec<Option<String>> = vec![None; self.blocks.blocks.len()];
+        while let Some(def) = def_blocks.pop() {
+            for &block in &self.frontiers[def].clone() {
+                if phis[block].is_none() && self.live_in[block].contains(var) {
+                    let name = self.fresh(var);
+                    let instrs = &mut self.blocks.blocks[block].instrs;
+                    instrs.insert(0, phi(name.clone(), op_type.clone(), Vec::new()));
+                    phis[block] = Some(name);
+                    def_blocks.push(block);
+                }
+            }
+        }
+        let entry = arg.map(|arg| arg.name.clone());
+        self.rename(var, &phis, 0, entry);
+    }
+
+    fn rename(&mut self, var: &str, phis: &[Option<String>], idx: usize, entry: Option<String>) {
+        let mut current = phis[idx].clone().or(entry);
+        let mut instrs = std::mem::take(&mut self.blocks.blocks[idx].instrs);
+        for instr in &mut instrs {
+            // Phi arguments are used at the end of the predecessor
+            if !util::is_value_op(instr, bril::ValueOps::Phi) {
+                if let (Some(args), Some(current)) = (get_args_mut(instr), &current) {
+                    for arg in args.iter_mut().filter(|arg| *arg == var) {
+                        *arg = current.clone();
+                    }
+                }
+            }
+            if util::get_dest(instr).is_some_and(|dest| dest == var) {
+                let name = self.fresh(var);
+                *util::unwrap_dest_mut(instr) = name.clone();
+                current = Some(name);
+            }
+        }
+        self.blocks.blocks[idx].instrs = instrs;
+
+        let label = self.blocks.blocks[idx].label.clone();
+        let mut succs = self.blocks.succ[idx].clone();
+        succs.sort_unstable();
+        succs.dedup();
+        for succ in succs {
+            for instr in &mut self.blocks.blocks[succ].instrs {
+                if !util::is_value_op(instr, bril::ValueOps::Phi) {
+                    break;
+                }
+                let inserted = phis[succ].as_ref() == util::get_dest(instr);
+                if let bril::Instruction::Value { args, labels, .. } = instr {
+                    if inserted {
+                        // A phi leaves out the edges its variable is undefined along
+                        if let Some(current) = &current {
+                            args.push(current.clone());
+                            labels.push(label.clone());
+                        }
+                        continue;
+                    }
+                    for (arg, _) in args
+                        .iter_mut()
+                        .zip(labels.iter())
+                        .filter(|(arg, arg_label)| *arg == var && **arg_label == label)
+                    {
+                        if let Some(current) = &current {
+                            *arg = current.clone();
+                        }
+                    }
+                }
+            }
+        }
+        for child in self.children[idx].clone() {
+            self.rename(var, phis, child, current.clone());
+        }
+    }
+}
+
+/// Where SSA construction places phis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhiPlacement {
+    /// At the iterated dominance frontier of every definition
+    Minimal,
+    /// Like minimal, but only for variables that are live across some block boundary
+    SemiPruned,
+    /// Only in blocks the variable is live into
+    Pruned,
+}
+
+// Variables used in some block before being defined in it
+fn global_variables(blocks: &bb::BasicBlocks) -> HashSet<String> {
+    let mut globals = HashSet::new();
+    for block in &blocks.blocks {
+        let mut killed = HashSet::new();
+        for instr in &block.instrs {
+            for arg in instr.args() {
+                if !killed.contains(arg) {
+                    globals.insert(arg.clone());
+                }
+            }
+            if let Some(dest) = util::get_dest(instr) {
+                killed.insert(dest);
+            }
+        }
+    }
+    globals
+}
+
 impl SSA {
     pub fn from_function(function: &bril::Function) -> SSA {
+        Self::from_function_with(function, PhiPlacement::Minimal)
+    }
+
+    pub fn from_function_with(function: &bril::Function, placement: PhiPlacement) -> SSA {
         let blocks = bb::BasicBlocks::from(&function.instrs);
         let mut defs = HashMap::new();
         let mut types = HashMap::new();
@@ -296,14 +1161,31 @@ impl SSA {
         let mut phis: Vec<BTreeSet<String>> =
             std::iter::repeat(BTreeSet::new()).take(cfg.len()).collect();
 
+        let globals = match placement {
+            PhiPlacement::SemiPruned => Some(global_variables(&blocks)),
+            _ => None,
+        };
+        let live_in = match placement {
+            PhiPlacement::Pruned => Some(analysis::live_variables(&blocks).1),
+            _ => None,
+        };
         // eprintln!("defs = {:?}", defs);
         for (var, mut defs) in defs {
+            if globals
+                .as_ref()
+                .is_some_and(|globals| !globals.contains(&var))
+            {
+                continue;
+            }
             let mut visited = HashSet::new();
             while let Some(&def) = defs.iter().next() {
                 let df = dom.dominance_frontier(def);
                 defs.remove(&def);
                 for (_pred, block) in df {
-                    if visited.insert(block.idx()) {
+                    let live = live_in
+                        .as_ref()
+                        .is_none_or(|live_in| live_in[block.idx()].contains(&var));
+                    if visited.insert(block.idx()) && live {
                         phis[block.idx()].insert(var.clone());
                         defs.insert(block.idx());
                     }
@@ -316,13 +1198,8 @@ impl SSA {
         for arg in &function.args {
             names.get_mut(&arg.name).unwrap().push(arg.name.clone());
         }
-        let (out_phis, out_blocks) = rename(
-            &phis,
-            &cfg,
-            &dom,
-            &types,
-            function.args.iter().map(|arg| &arg.name),
-        );
+        let (out_phis, out_blocks) =
+            rename(&phis, &cfg, &dom, function.args.iter().map(|arg| &arg.name));
         let iter = izip!(
             out_phis.into_iter(),
             out_blocks.into_iter(),
@@ -338,72 +1215,181 @@ impl SSA {
                 bb::BasicBlock { instrs, label }
             })
             .collect();
-        let mut blocks = bb::BasicBlocks::from_blocks(blocks);
-        add_undefined_vars(&mut blocks, &types);
+        let blocks = bb::BasicBlocks::from_blocks(blocks);
         let mut function = function.clone();
         function.instrs = blocks.to_instrs();
 
         SSA { function }
     }
 
+    /**
+     * Like `from_function_with`, but in the `set`/`get` form instead of phis.
+     */
+    pub fn from_function_set_get(function: &bril::Function, placement: PhiPlacement) -> SSA {
+        Self::from_function_with(function, placement).to_set_get()
+    }
+
+    /**
+     * Converts phis into `set`s and `get`s.
+     */
+    pub fn to_set_get(self) -> SSA {
+        SSA {
+            function: phis_to_set_get(&self.function),
+        }
+    }
+
+    /**
+     * Converts `set`s and `get`s into phis.
+     */
+    pub fn to_phis(self) -> SSA {
+        SSA {
+            function: set_get_to_phis(&self.function),
+        }
+    }
+
+    /**
+     * Restores single assignment for every variable with several definitions.
+     */
+    pub fn repair(self) -> SSA {
+        let mut counts: BTreeMap<&String, usize> = BTreeMap::new();
+        let defs = self.function.args.iter().map(|arg| &arg.name).chain(
+            self.function
+                .instrs
+                .iter()
+                .filter_map(util::get_instr)
+                .filter_map(util::get_dest),
+        );
+        for def in defs {
+            *counts.entry(def).or_default() += 1;
+        }
+        let mut blocks = bb::BasicBlocks::from(&self.function.instrs);
+        let mut updater = SSAUpdater::new(&mut blocks, &self.function.args);
+        for (var, _) in counts.into_iter().filter(|&(_, count)| count > 1) {
+            updater.update(var);
+        }
+        SSA {
+            function: bril::Function {
+                instrs: blocks.to_instrs(),
+                ..self.function.clone()
+            },
+        }
+    }
+
+    /**
+     * Checks that the function is in SSA form, in either representation.
+     * The `set`/`get` form is checked as it is, so that errors point at the
+     * `set`s rather than at the phis they would turn into.
+     */
+    pub fn verify(&self) -> Result<(), Vec<SSAError>> {
+        let errors = verify(&self.function);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn is_set_get(&self) -> bool {
+        self.function
+            .instrs
+            .iter()
+            .filter_map(util::get_instr)
+            .any(|instr| util::is_value_op(instr, bril::ValueOps::Get))
+    }
+
+    /**
+     * Translates out of SSA form. The copies implied by the phis on each edge
+     * happen in parallel, so they are sequentialized, using a temporary to
+     * break cycles. Copies go at the end of the predecessor or the start of
+     * the successor where possible, and only critical edges get a new block.
+     * Phi webs that don't interfere share a name, so most copies disappear.
+     * Functions in the `set`/`get` form go through phis first.
+     */
     pub fn from_ssa(self) -> bril::Function {
+        if self.is_set_get() {
+            return self.to_phis().from_ssa();
+        }
         let mut blocks = bb::BasicBlocks::from(&self.function.instrs);
+        let phis = remove_phis(&mut blocks);
+        let (_, live_out) = ssa_liveness(&blocks, &phis);
+        let interference = Interference::new(&blocks, &phis, &live_out);
+        let mut webs = Webs::new(self.function.args.iter().map(|arg| &arg.name));
+        for phi in phis.iter().flatten() {
+            for (_, arg) in &phi.args {
+                webs.coalesce(&phi.dest, arg, &interference);
+            }
+        }
+        for block in &mut blocks.blocks {
+            for instr in &mut block.instrs {
+                if let Some(args) = get_args_mut(instr) {
+                    for arg in args {
+                        *arg = webs.find(arg);
+                    }
+                }
+                if util::get_dest(instr).is_some() {
+                    let dest = util::unwrap_dest_mut(instr);
+                    *dest = webs.find(dest);
+                }
+            }
+        }
 
-        // let mut rewrites = Vec::new();
-        let mut rewrites = BTreeMap::new();
-        for idx in 0..blocks.blocks.len() {
-            let block = &blocks.blocks[idx];
-            let mut instrs = Vec::new();
-            for instr in &block.instrs {
-                if util::is_value_op(instr, bril::ValueOps::Phi) {
-                    let dest = util::unwrap_dest(instr);
-                    let typ = util::unwrap_type(instr);
-                    let args = util::get_args(instr).unwrap();
-                    let labels = util::get_labels(instr).unwrap();
-                    for (arg, label) in args.iter().zip(labels.iter()) {
-                        let pred = blocks.labels[label];
-                        rewrites.entry((pred, idx)).or_insert(Vec::new()).push((
-                            typ.clone(),
-                            dest.clone(),
-                            arg.clone(),
-                        ));
+        // The parallel copy on each edge, keyed by (pred, block)
+        let mut copies: BTreeMap<(usize, usize), ParallelCopy> = BTreeMap::new();
+        for (idx, phis) in phis.iter().enumerate() {
+            for phi in phis {
+                let dest = webs.find(&phi.dest);
+                for (label, arg) in &phi.args {
+                    let arg = webs.find(arg);
+                    if arg != dest {
+                        copies
+                            .entry((blocks.labels[label], idx))
+                            .or_default()
+                            .push((phi.op_type.clone(), dest.clone(), arg));
                     }
-                } else {
-                    instrs.push(instr.clone());
                 }
             }
-            blocks.blocks[idx].instrs = instrs;
         }
 
-        for ((pred_idx, block_idx), vars) in rewrites {
-            let old_label = blocks.blocks[block_idx].label.clone();
-            let new_label = blocks.create_label();
-            // Add a block labelled new_label that defines all the variables
-            // needed along the edge (pred_idx, block_idx)
-            {
-                let mut defined = HashSet::new(); // Variables defined in this block
+        let prefix = "_".repeat(util::max_underscores(&self.function) + 1);
+        let mut temps = 0;
+        for ((pred, idx), copies) in copies {
+            let mut fresh = || {
+                temps += 1;
+                format!("{}tmp{}", prefix, temps - 1)
+            };
+            let copies = sequentialize(copies, &mut fresh);
+            let pred_jumps = matches!(
+                blocks.blocks[pred].instrs.last(),
+                Some(bril::Instruction::Effect {
+                    op: bril::EffectOps::Jump,
+                    ..
+                })
+            );
+            if pred_jumps {
+                let instrs = &mut blocks.blocks[pred].instrs;
+                let terminator = instrs.pop().unwrap();
+                instrs.extend(copies);
+                instrs.push(terminator);
+            } else if blocks.pred[idx].len() == 1 {
+                let instrs = &mut blocks.blocks[idx].instrs;
+                instrs.splice(0..0, copies);
+            } else {
+                // Critical edge, the copies get a block of their own
+                let old_label = blocks.blocks[idx].label.clone();
+                let new_label = blocks.create_label();
                 let mut block = bb::BasicBlock::from(new_label.clone());
-                // Define each variable
-                for (typ, dest, arg) in vars {
-                    block
-                        .instrs
-                        .push(bril::Instruction::id(typ, dest.clone(), arg));
-                    defined.insert(dest);
-                }
-                // Jump to the target block
+                block.instrs.extend(copies);
                 block
                     .instrs
                     .push(bril::Instruction::jump(old_label.clone()));
-                blocks.blocks.push(block);
+                blocks.add(block);
+                let terminator = blocks.blocks[pred].instrs.last_mut().unwrap();
+                for label in util::get_labels_mut(terminator).unwrap() {
+                    if *label == old_label {
+                        *label = new_label.clone();
+                    }
+                }
             }
-            // Rewrite labels in predecessor: old_label -> new_label
-            blocks.blocks[pred_idx]
-                .instrs
-                .iter_mut()
-                .filter_map(|instr| util::get_labels_mut(instr))
-                .flat_map(|labels| labels.iter_mut())
-                .filter(|label| *label == &old_label)
-                .for_each(|label| *label = new_label.clone());
         }
         let instrs = blocks.to_instrs();
         bril::Function {
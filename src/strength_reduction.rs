@@ -0,0 +1,546 @@
+use super::cfg::{find_loops, insert_preheaders, Loop};
+use super::{bb, bril, util};
+use std::collections::{HashMap, HashSet};
+
+// Induction variable strength reduction and linear-function test replacement.
+// Expects its input to be in SSA form, basic induction variables are found
+// as phis in the loop header.
+
+/// A product of loop-invariant variables, the empty product is 1.
+type Product = Vec<String>;
+
+/// The value `base + scale * iv + offset` where `iv` is a basic induction
+/// variable, and every other term is loop-invariant.
+#[derive(Clone, Debug)]
+struct Affine {
+    iv: String,
+    base: Option<String>,
+    scale: Product,
+    /// A sum of products, each of which may be negated
+    offset: Vec<(bool, Product)>,
+}
+
+impl Affine {
+    fn basic(iv: &str) -> Self {
+        Affine {
+            iv: iv.to_string(),
+            base: None,
+            scale: Vec::new(),
+            offset: Vec::new(),
+        }
+    }
+
+    fn add(&self, negate: bool, term: &str) -> Self {
+        let mut affine = self.clone();
+        affine.offset.push((negate, vec![term.to_string()]));
+        affine
+    }
+
+    fn mul(&self, factor: &str) -> Self {
+        let mut affine = self.clone();
+        affine.scale.push(factor.to_string());
+        for (_, product) in &mut affine.offset {
+            product.push(factor.to_string());
+        }
+        affine
+    }
+}
+
+struct BasicInductionVariable {
+    init: String,
+    next: String,
+    step: String,
+    negate: bool,
+}
+
+/// Hands out variable names that don't clash with any existing variable.
+struct Names {
+    used: HashSet<String>,
+}
+
+impl Names {
+    fn new(function: &bril::Function) -> Self {
+        Names {
+            used: util::get_referenced_variables(function).cloned().collect(),
+        }
+    }
+
+    fn fresh(&mut self, name: &str) -> String {
+        let mut fresh = name.to_string();
+        let mut idx = 0;
+        while self.used.contains(&fresh) {
+            fresh = format!("{}.{}", name, idx);
+            idx += 1;
+        }
+        self.used.insert(fresh.clone());
+        fresh
+    }
+}
+
+/// Emits the loop-invariant computations into the preheader.
+struct Builder<'a> {
+    names: &'a mut Names,
+    instrs: Vec<bril::Instruction>,
+}
+
+impl<'a> Builder<'a> {
+    fn value(
+        &mut self,
+        op: bril::ValueOps,
+        op_type: bril::Type,
+        name: &str,
+        args: Vec<String>,
+    ) -> String {
+        let dest = self.names.fresh(name);
+        self.instrs.push(bril::Instruction::Value {
+            op,
+            op_type,
+            dest: dest.clone(),
+            args,
+            funcs: Vec::new(),
+            labels: Vec::new(),
+        });
+        dest
+    }
+
+    fn product(&mut self, name: &str, product: &[String]) -> String {
+        match product {
+            [] => {
+                let dest = self.names.fresh(name);
+                self.instrs
+                    .push(bril::Instruction::const_int(dest.clone(), 1));
+                dest
+            }
+            [first, rest @ ..] => rest.iter().fold(first.clone(), |acc, factor| {
+                self.value(
+                    bril::ValueOps::Mul,
+                    bril::Type::Int,
+                    name,
+                    vec![acc, factor.clone()],
+                )
+            }),
+        }
+    }
+
+    fn negate(&mut self, name: &str, var: String) -> String {
+        let zero = self.names.fresh(name);
+        self.instrs
+            .push(bril::Instruction::const_int(zero.clone(), 0));
+        self.value(bril::ValueOps::Sub, bril::Type::Int, name, vec![zero, var])
+    }
+
+    /// Evaluates the affine function at `value` of its induction variable.
+    fn evaluate(
+        &mut self,
+        name: &str,
+        op_type: &bril::Type,
+        affine: &Affine,
+        value: &str,
+    ) -> String {
+        let mut acc = value.to_string();
+        if !affine.scale.is_empty() {
+            let scale = self.product(name, &affine.scale);
+            acc = self.value(bril::ValueOps::Mul, bril::Type::Int, name, vec![acc, scale]);
+        }
+        for (negate, product) in &affine.offset {
+            let term = self.product(name, product);
+            let op = if *negate {
+                bril::ValueOps::Sub
+            } else {
+                bril::ValueOps::Add
+            };
+            acc = self.value(op, bril::Type::Int, name, vec![acc, term]);
+        }
+        match &affine.base {
+            Some(base) => self.value(
+                bril::ValueOps::PtrAdd,
+                op_type.clone(),
+                name,
+                vec![base.clone(), acc],
+            ),
+            None => acc,
+        }
+    }
+}
+
+fn is_phi(instr: &bril::Instruction) -> bool {
+    util::is_value_op(instr, bril::ValueOps::Phi)
+}
+
+fn value_op(instr: &bril::Instruction) -> Option<(bril::ValueOps, &[String])> {
+    match instr {
+        bril::Instruction::Value { op, args, .. } => Some((*op, args)),
+        _ => None,
+    }
+}
+
+// Returns the value of a variable defined by a constant, if it is one
+fn constant_int(
+    blocks: &bb::BasicBlocks,
+    defs: &HashMap<String, (usize, usize)>,
+    var: &str,
+) -> Option<i64> {
+    let &(block, idx) = defs.get(var)?;
+    match &blocks.blocks[block].instrs[idx] {
+        bril::Instruction::Constant {
+            value: bril::Literal::Int(value),
+            ..
+        } => Some(*value),
+        _ => None,
+    }
+}
+
+fn reduce_loop(blocks: &mut bb::BasicBlocks, natural_loop: &Loop, names: &mut Names) {
+    let mut defs = HashMap::new();
+    for (block, bb) in blocks.blocks.iter().enumerate() {
+        for (idx, instr) in bb.instrs.iter().enumerate() {
+            if let Some(dest) = util::get_dest(instr) {
+                defs.insert(dest.clone(), (block, idx));
+            }
+        }
+    }
+    let is_invariant = |var: &String| {
+        defs.get(var)
+            .is_none_or(|(block, _)| !natural_loop.blocks.contains(block))
+    };
+
+    // Basic induction variables: i = phi init next, next = i + step
+    let preheader_label = &blocks.blocks[natural_loop.preheader].label;
+    let mut basics = HashMap::new();
+    for instr in &blocks.blocks[natural_loop.header].instrs {
+        if !is_phi(instr) {
+            continue;
+        }
+        let dest = util::unwrap_dest(instr);
+        let args = instr.args();
+        let labels = util::get_labels(instr).unwrap();
+        let init = args
+            .iter()
+            .zip(labels.iter())
+            .find(|(_, label)| *label == preheader_label)
+            .map(|(arg, _)| arg);
+        let mut latch_args = args
+            .iter()
+            .zip(labels.iter())
+            .filter(|(_, label)| *label != preheader_label)
+            .map(|(arg, _)| arg);
+        let next = match (init, latch_args.next()) {
+            (Some(_), Some(next)) if latch_args.all(|arg| arg == next) => next,
+            _ => continue,
+        };
+        let (block, idx) = match defs.get(next) {
+            Some(&(block, idx)) if natural_loop.blocks.contains(&block) => (block, idx),
+            _ => continue,
+        };
+        let (step, negate) = match value_op(&blocks.blocks[block].instrs[idx]) {
+            Some((bril::ValueOps::Add, [x, y])) if x == dest && is_invariant(y) => (y, false),
+            Some((bril::ValueOps::Add, [x, y])) if y == dest && is_invariant(x) => (x, false),
+            Some((bril::ValueOps::Sub, [x, y])) if x == dest && is_invariant(y) => (y, true),
+            _ => continue,
+        };
+        basics.insert(
+            dest.clone(),
+            BasicInductionVariable {
+                init: init.unwrap().clone(),
+                next: next.clone(),
+                step: step.clone(),
+                negate,
+            },
+        );
+    }
+    if basics.is_empty() {
+        return;
+    }
+
+    // Derived induction variables: affine functions of a basic induction variable
+    let mut affine: HashMap<String, Affine> = basics
+        .keys()
+        .map(|iv| (iv.clone(), Affine::basic(iv)))
+        .collect();
+    let mut derived = Vec::new();
+    let loop_blocks = &natural_loop.blocks;
+    loop {
+        let mut changed = false;
+        for &block in loop_blocks {
+            for instr in &blocks.blocks[block].instrs {
+                let dest = match util::get_dest(instr) {
+                    Some(dest) if !affine.contains_key(dest) => dest,
+                    _ => continue,
+                };
+                let integer = |var: &String| affine.get(var).filter(|f| f.base.is_none());
+                let function = match value_op(instr) {
+                    Some((bril::ValueOps::Id, [x])) => affine.get(x).cloned(),
+                    Some((bril::ValueOps::Add, [x, y])) if is_invariant(y) => {
+                        integer(x).map(|f| f.add(false, y))
+                    }
+                    Some((bril::ValueOps::Add, [x, y])) if is_invariant(x) => {
+                        integer(y).map(|f| f.add(false, x))
+                    }
+                    Some((bril::ValueOps::Sub, [x, y])) if is_invariant(y) => {
+                        integer(x).map(|f| f.add(true, y))
+                    }
+                    Some((bril::ValueOps::Mul, [x, y])) if is_invariant(y) => {
+                        integer(x).map(|f| f.mul(y))
+                    }
+                    Some((bril::ValueOps::Mul, [x, y])) if is_invariant(x) => {
+                        integer(y).map(|f| f.mul(x))
+                    }
+                    Some((bril::ValueOps::PtrAdd, [p, x])) if is_invariant(p) => {
+                        integer(x).map(|f| {
+                            let mut f = f.clone();
+                            f.base = Some(p.clone());
+                            f
+                        })
+                    }
+                    Some((bril::ValueOps::PtrAdd, [p, x])) if is_invariant(x) => affine
+                        .get(p)
+                        .filter(|f| f.base.is_some())
+                        .map(|f| f.add(false, x)),
+                    _ => None,
+                };
+                if let Some(function) = function {
+                    affine.insert(dest.clone(), function);
+                    derived.push((block, dest.clone()));
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Only reduce multiplications and pointer arithmetic, and skip those that
+    // are only used to compute other reduced induction variables.
+    let mut users: HashMap<&String, Vec<Option<&String>>> = HashMap::new();
+    for bb in &blocks.blocks {
+        for instr in &bb.instrs {
+            for arg in instr.args() {
+                users.entry(arg).or_default().push(util::get_dest(instr));
+            }
+        }
+    }
+    let mut reduced: HashSet<&String> = HashSet::new();
+    for (block, var) in derived.iter().rev() {
+        let (_, idx) = defs[var];
+        let op = value_op(&blocks.blocks[*block].instrs[idx]).unwrap().0;
+        if op != bril::ValueOps::Mul && op != bril::ValueOps::PtrAdd {
+            continue;
+        }
+        let needed = users.get(var).is_some_and(|users| {
+            users
+                .iter()
+                .any(|user| !user.is_some_and(|user| reduced.contains(user)))
+        });
+        if needed {
+            reduced.insert(var);
+        }
+    }
+    if reduced.is_empty() {
+        return;
+    }
+    let mut reduced: Vec<_> = derived
+        .iter()
+        .map(|(_, var)| var)
+        .filter(|var| reduced.contains(var))
+        .cloned()
+        .collect();
+    reduced.sort();
+
+    let mut builder = Builder {
+        names,
+        instrs: Vec::new(),
+    };
+    let mut phis = Vec::new();
+    let mut updates: HashMap<String, Vec<bril::Instruction>> = HashMap::new();
+    let mut replacements = HashMap::new();
+    // The reduced variable tracking each derived one at the header and after the update
+    let mut tracking = HashMap::new();
+    for var in &reduced {
+        let (block, idx) = defs[var];
+        let op_type = util::unwrap_type(&blocks.blocks[block].instrs[idx]);
+        let function = &affine[var];
+        let basic = &basics[&function.iv];
+
+        let init = builder.evaluate(&format!("{}.init", var), &op_type, function, &basic.init);
+        let mut step_product = function.scale.clone();
+        step_product.push(basic.step.clone());
+        let mut step = builder.product(&format!("{}.step", var), &step_product);
+        if basic.negate {
+            step = builder.negate(&format!("{}.step", var), step);
+        }
+
+        let current = builder.names.fresh(&format!("{}.sr", var));
+        let next = builder.names.fresh(&format!("{}.sr.next", var));
+        let header_labels = blocks.blocks[natural_loop.header]
+            .instrs
+            .iter()
+            .find(|instr| is_phi(instr) && util::get_dest(instr) == Some(&function.iv))
+            .map(|instr| util::get_labels(instr).unwrap().clone())
+            .unwrap();
+        let (labels, args) = header_labels
+            .into_iter()
+            .map(|label| {
+                let arg = if &label == preheader_label {
+                    init.clone()
+                } else {
+                    next.clone()
+                };
+                (label, arg)
+            })
+            .unzip();
+        phis.push(bril::Instruction::Value {
+            op: bril::ValueOps::Phi,
+            op_type: op_type.clone(),
+            dest: current.clone(),
+            args,
+            funcs: Vec::new(),
+            labels,
+        });
+        let update_op = if function.base.is_some() {
+            bril::ValueOps::PtrAdd
+        } else {
+            bril::ValueOps::Add
+        };
+        updates
+            .entry(basic.next.clone())
+            .or_default()
+            .push(bril::Instruction::Value {
+                op: update_op,
+                op_type: op_type.clone(),
+                dest: next.clone(),
+                args: vec![current.clone(), step],
+                funcs: Vec::new(),
+                labels: Vec::new(),
+            });
+        replacements.insert(
+            var.clone(),
+            bril::Instruction::id(op_type, var.clone(), current.clone()),
+        );
+        tracking.insert(var.clone(), (current, next));
+    }
+
+    // Linear-function test replacement: compare a reduced variable against the
+    // bound scaled the same way, so the basic induction variable may become dead.
+    let mut tests = HashMap::new();
+    for &block in loop_blocks {
+        let terminator = blocks.blocks[block].instrs.last().unwrap();
+        let cond = match terminator {
+            bril::Instruction::Effect {
+                op: bril::EffectOps::Branch,
+                args,
+                ..
+            } => &args[0],
+            _ => continue,
+        };
+        let (cond_block, cond_idx) = match defs.get(cond) {
+            Some(&def) if natural_loop.blocks.contains(&def.0) => def,
+            _ => continue,
+        };
+        let (op, args) = match value_op(&blocks.blocks[cond_block].instrs[cond_idx]) {
+            Some((op, [x, y])) => (op, [x, y]),
+            _ => continue,
+        };
+        if !matches!(
+            op,
+            bril::ValueOps::Lt
+                | bril::ValueOps::Le
+                | bril::ValueOps::Gt
+                | bril::ValueOps::Ge
+                | bril::ValueOps::Eq
+        ) {
+            continue;
+        }
+        // One side must be a basic induction variable (or its update), the other invariant
+        let position = match (&affine.get(args[0]), &affine.get(args[1])) {
+            (Some(_), None) if is_invariant(args[1]) => 0,
+            (None, Some(_)) if is_invariant(args[0]) => 1,
+            _ => continue,
+        };
+        let iv_arg = args[position];
+        let bound = args[1 - position];
+        let iv = &affine[iv_arg].iv;
+        let at_update = if iv_arg == iv {
+            false
+        } else if iv_arg == &basics[iv].next {
+            true
+        } else {
+            continue;
+        };
+        // The scale has to be positive to preserve the comparison
+        let candidate = reduced.iter().find(|var| {
+            let function = &affine[*var];
+            &function.iv == iv
+                && function.base.is_none()
+                && !function.scale.is_empty()
+                && function.scale.iter().all(|factor| {
+                    constant_int(blocks, &defs, factor).is_some_and(|value| value > 0)
+                })
+        });
+        let var = match candidate {
+            Some(var) => var,
+            None => continue,
+        };
+        let new_bound = builder.evaluate(
+            &format!("{}.bound", cond),
+            &bril::Type::Int,
+            &affine[var],
+            bound,
+        );
+        let (current, next) = &tracking[var];
+        let reduced_arg = if at_update { next } else { current };
+        let mut new_args = vec![String::new(), String::new()];
+        new_args[position] = reduced_arg.clone();
+        new_args[1 - position] = new_bound;
+        tests.insert((cond_block, cond_idx), new_args);
+    }
+
+    let Builder {
+        instrs: hoisted, ..
+    } = builder;
+    for (&(block, idx), new_args) in &tests {
+        if let bril::Instruction::Value { args, .. } = &mut blocks.blocks[block].instrs[idx] {
+            *args = new_args.clone();
+        }
+    }
+    for &block in loop_blocks {
+        let instrs = std::mem::take(&mut blocks.blocks[block].instrs);
+        let mut new_instrs = Vec::new();
+        if block == natural_loop.header {
+            new_instrs.extend(phis.iter().cloned());
+        }
+        for instr in instrs {
+            let dest = util::get_dest(&instr).cloned();
+            match dest.as_ref().and_then(|dest| replacements.remove(dest)) {
+                Some(replacement) => new_instrs.push(replacement),
+                None => new_instrs.push(instr),
+            }
+            if let Some(update) = dest.and_then(|dest| updates.remove(&dest)) {
+                new_instrs.extend(update);
+            }
+        }
+        blocks.blocks[block].instrs = new_instrs;
+    }
+    let preheader = &mut blocks.blocks[natural_loop.preheader].instrs;
+    let terminator = preheader.pop().unwrap();
+    preheader.extend(hoisted);
+    preheader.push(terminator);
+}
+
+fn function_strength_reduction(function: &bril::Function) -> bb::BasicBlocks {
+    let mut blocks = insert_preheaders(bb::BasicBlocks::from(&function.instrs));
+    let loops = find_loops(&blocks);
+    let mut names = Names::new(function);
+    for natural_loop in &loops {
+        reduce_loop(&mut blocks, natural_loop, &mut names);
+    }
+    blocks
+}
+
+pub fn strength_reduction(program: &bril::Program) -> bril::Program {
+    let mut sr_program = program.clone();
+    for func in &mut sr_program.functions {
+        func.instrs = function_strength_reduction(func).to_instrs();
+    }
+    sr_program
+}
@@ -0,0 +1,135 @@
+use super::{bril, util};
+
+/// Returns the arguments of a call to `name` whose result is returned as is,
+/// possibly after falling through some labels.
+fn tail_call<'a>(
+    name: &str,
+    instr: &'a bril::Instruction,
+    rest: &[bril::Code],
+) -> Option<&'a Vec<String>> {
+    let next = rest.iter().find_map(util::get_instr);
+    match instr {
+        bril::Instruction::Value {
+            op: bril::ValueOps::Call,
+            dest,
+            args,
+            funcs,
+            ..
+        } if funcs[0] == name => match next {
+            Some(next) if next.is_return() && next.args() == [dest.clone()] => Some(args),
+            _ => None,
+        },
+        bril::Instruction::Effect {
+            op: bril::EffectOps::Call,
+            args,
+            funcs,
+            ..
+        } if funcs[0] == name => match next {
+            // Falling off the end is a void return
+            Some(next) if next.is_return() && next.args().is_empty() => Some(args),
+            None => Some(args),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Turns self-calls in tail position into a jump back to the start of the
+/// function. The new arguments are all evaluated before any parameter is
+/// overwritten, since they may refer to each other (e.g. `call @f b a`).
+fn function_tce(function: &bril::Function) -> bril::Function {
+    let has_tail_call = function.instrs.iter().enumerate().any(|(idx, code)| {
+        util::get_instr(code)
+            .and_then(|instr| tail_call(&function.name, instr, &function.instrs[idx + 1..]))
+            .is_some()
+    });
+    if !has_tail_call {
+        return function.clone();
+    }
+    let prefix = "_".repeat(util::max_underscores(function) + 1);
+    let entry_label = format!("{}entry", prefix);
+    let header_label = format!("{}tail", prefix);
+
+    let mut tce_function = function.clone();
+    // Keep an empty entry block, so the loop header has a predecessor outside the loop
+    tce_function.instrs = vec![
+        bril::Code::Label { label: entry_label },
+        bril::Code::Label {
+            label: header_label.clone(),
+        },
+    ];
+    let mut skip_return = false;
+    for (idx, code) in function.instrs.iter().enumerate() {
+        if skip_return {
+            skip_return = false;
+            continue;
+        }
+        let args = util::get_instr(code)
+            .and_then(|instr| tail_call(&function.name, instr, &function.instrs[idx + 1..]));
+        let args = match args {
+            Some(args) => args,
+            None => {
+                tce_function.instrs.push(code.clone());
+                continue;
+            }
+        };
+        let mut changed: Vec<_> = function
+            .args
+            .iter()
+            .zip(args)
+            .filter(|(param, arg)| &param.name != *arg)
+            .collect();
+        // Only go through temporaries if an argument reads a parameter being assigned
+        let overlaps = changed
+            .iter()
+            .any(|(_, arg)| changed.iter().any(|(param, _)| &&param.name == arg));
+        if !overlaps {
+            for (param, arg) in changed.drain(..) {
+                tce_function
+                    .instrs
+                    .push(bril::Code::Instruction(bril::Instruction::id(
+                        param.arg_type.clone(),
+                        param.name.clone(),
+                        arg.clone(),
+                    )));
+            }
+        }
+        for (param, arg) in &changed {
+            tce_function
+                .instrs
+                .push(bril::Code::Instruction(bril::Instruction::id(
+                    param.arg_type.clone(),
+                    format!("{}tail.{}", prefix, param.name),
+                    (*arg).clone(),
+                )));
+        }
+        for (param, _) in &changed {
+            tce_function
+                .instrs
+                .push(bril::Code::Instruction(bril::Instruction::id(
+                    param.arg_type.clone(),
+                    param.name.clone(),
+                    format!("{}tail.{}", prefix, param.name),
+                )));
+        }
+        tce_function
+            .instrs
+            .push(bril::Code::Instruction(bril::Instruction::jump(
+                header_label.clone(),
+            )));
+        // A return right after the call is now unreachable
+        skip_return = matches!(
+            function.instrs.get(idx + 1),
+            Some(bril::Code::Instruction(_))
+        );
+    }
+    tce_function
+}
+
+pub fn tce(program: &bril::Program) -> bril::Program {
+    let mut tce_program = program.clone();
+    for func in &mut tce_program.functions {
+        *func = function_tce(func);
+    }
+    tce_program
+}
@@ -59,6 +59,8 @@ pub fn evaluate(op: &bril::ValueOps, args: &Vec<bril::Literal>) -> bril::Literal
                 panic!("All args must be identical!");
             }
         }
+        bril::ValueOps::Get => panic!("Unsupported!"),
+        bril::ValueOps::Undef => panic!("Unsupported!"),
         bril::ValueOps::Call => panic!("Unsupported!"),
         bril::ValueOps::Id => args[0].clone(),
         bril::ValueOps::Alloc => panic!("Unsupported!"),
@@ -199,4 +201,18 @@ pub fn get_referenced_variables(function: &bril::Function) -> impl Iterator<Item
         .filter_map(|instr| get_args(instr))
         .flat_map(|args| args.iter());
     args_iter.chain(instr_dest_iter).chain(instr_args_iter)
-}
\ No newline at end of file
+}
+
+// Most underscores in any variable or label, a name prefixed by more
+// underscores than this can't clash with anything in the function
+pub fn max_underscores(function: &bril::Function) -> usize {
+    let labels = function.instrs.iter().filter_map(|code| match code {
+        bril::Code::Label { label } => Some(label),
+        _ => None,
+    });
+    get_referenced_variables(function)
+        .chain(labels)
+        .map(|name| name.chars().filter(|c| c == &'_').count())
+        .max()
+        .unwrap_or(0)
+}
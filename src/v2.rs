@@ -1,9 +1,17 @@
 pub mod graph;
+pub mod algorithms;
 pub mod context;
 pub mod linked_list;
 pub mod basic_block;
 pub mod control_flow_graph;
+pub mod function;
+pub mod dce;
 pub mod error;
 pub mod ssa;
 pub mod dominance_tree;
-pub mod instruction;
\ No newline at end of file
+pub mod post_dominance_tree;
+pub mod control_dependence_graph;
+pub mod instruction;
+pub mod loops;
+pub mod reducibility;
+pub mod dot;
\ No newline at end of file
@@ -0,0 +1,593 @@
+use super::graph::Graph;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/**
+ * The strongly connected components of a graph, found with Tarjan's
+ * algorithm. Every node is visited, not only the ones reachable from the
+ * entry, so graphs without an entry like call graphs work too. As a graph it
+ * is the condensation: one node per component, numbered in the order
+ * Tarjan's algorithm finds them, with an edge between two components if any
+ * of their members have one.
+ */
+pub struct StronglyConnectedComponents<N> {
+    /** Reverse topological order: components come after their successors */
+    components: Vec<Vec<N>>,
+    component: HashMap<N, usize>,
+    /** Whether a path leads from each component back into itself */
+    cyclic: Vec<bool>,
+    entry: Option<usize>,
+    exit: Option<usize>,
+    pred: Vec<Vec<usize>>,
+    succ: Vec<Vec<usize>>,
+}
+
+struct Tarjan<'a, G: Graph> {
+    graph: &'a G,
+    next_index: usize,
+    index: HashMap<G::Node, usize>,
+    low_link: HashMap<G::Node, usize>,
+    on_stack: HashSet<G::Node>,
+    stack: Vec<G::Node>,
+    components: Vec<Vec<G::Node>>,
+}
+
+impl<'a, G: Graph> Tarjan<'a, G> {
+    /**
+     * Searches from `root` with an explicit stack of the nodes being visited
+     * and the successors they have left, so that long chains don't overflow
+     * the call stack.
+     */
+    fn visit(&mut self, root: G::Node) {
+        let mut calls = vec![self.enter(root)];
+        while let Some((node, succs)) = calls.last_mut() {
+            let node = *node;
+            match succs.next() {
+                Some(succ) => match self.index.get(&succ).copied() {
+                    None => calls.push(self.enter(succ)),
+                    Some(index) if self.on_stack.contains(&succ) => {
+                        let low_link = self.low_link[&node].min(index);
+                        self.low_link.insert(node, low_link);
+                    }
+                    Some(_) => {}
+                },
+                None => {
+                    calls.pop();
+                    self.leave(node);
+                    if let Some((parent, _)) = calls.last() {
+                        let low_link = self.low_link[parent].min(self.low_link[&node]);
+                        self.low_link.insert(*parent, low_link);
+                    }
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, node: G::Node) -> (G::Node, std::vec::IntoIter<G::Node>) {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.index.insert(node, index);
+        self.low_link.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+        let succs: Vec<_> = self.graph.successors(node).collect();
+        (node, succs.into_iter())
+    }
+
+    /**
+     * Pops the component rooted at `node` once all its successors are done.
+     */
+    fn leave(&mut self, node: G::Node) {
+        if self.low_link[&node] == self.index[&node] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(&member);
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+impl<N: Copy + Eq + Hash + Debug> StronglyConnectedComponents<N> {
+    /**
+     * The search starts at the entry and then at every node not yet visited,
+     * in the order the graph lists them.
+     */
+    pub fn new<G: Graph<Node = N>>(graph: &G) -> Self {
+        let mut tarjan = Tarjan {
+            graph,
+            next_index: 0,
+            index: HashMap::new(),
+            low_link: HashMap::new(),
+            on_stack: HashSet::new(),
+            stack: Vec::new(),
+            components: Vec::new(),
+        };
+        for node in graph.entry_node().into_iter().chain(graph.nodes()) {
+            if !tarjan.index.contains_key(&node) {
+                tarjan.visit(node);
+            }
+        }
+        let components = tarjan.components;
+        let component: HashMap<_, _> = components
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, members)| members.iter().map(move |member| (*member, idx)))
+            .collect();
+
+        let mut cyclic: Vec<_> = components.iter().map(|members| members.len() > 1).collect();
+        let mut pred = vec![Vec::new(); components.len()];
+        let mut succ = vec![Vec::new(); components.len()];
+        for (idx, members) in components.iter().enumerate() {
+            for member in members.iter() {
+                for target in graph.successors(*member) {
+                    let target = component[&target];
+                    if target == idx {
+                        cyclic[idx] = true;
+                    } else if !succ[idx].contains(&target) {
+                        succ[idx].push(target);
+                        pred[target].push(idx);
+                    }
+                }
+            }
+        }
+        StronglyConnectedComponents {
+            entry: graph.entry_node().map(|node| component[&node]),
+            exit: graph.exit_node().map(|node| component[&node]),
+            components,
+            component,
+            cyclic,
+            pred,
+            succ,
+        }
+    }
+
+    /**
+     * All components in reverse topological order, so each one comes after
+     * every component it has an edge to.
+     */
+    pub fn components(&self) -> &[Vec<N>] {
+        &self.components
+    }
+
+    pub fn members(&self, idx: usize) -> &[N] {
+        &self.components[idx]
+    }
+
+    pub fn component(&self, node: N) -> usize {
+        self.component[&node]
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /**
+     * Whether the component contains a cycle: it has several members, or its
+     * only member has an edge to itself.
+     */
+    pub fn is_cyclic(&self, idx: usize) -> bool {
+        self.cyclic[idx]
+    }
+
+    /**
+     * The components ordered so that every edge of the condensation goes
+     * forward.
+     */
+    pub fn topological_order(&self) -> impl Iterator<Item = usize> {
+        (0..self.components.len()).rev()
+    }
+}
+
+impl<N: Copy + Eq + Hash + Debug> Graph for StronglyConnectedComponents<N> {
+    type Node = usize;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        self.entry
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        self.exit
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        0..self.components.len()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.pred[node].iter().copied()
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.succ[node].iter().copied()
+    }
+}
+
+pub fn strongly_connected_components<G: Graph>(graph: &G) -> StronglyConnectedComponents<G::Node> {
+    StronglyConnectedComponents::new(graph)
+}
+
+/**
+ * The nodes reachable from `node` by following zero or more edges.
+ */
+pub fn reachable_from<G: Graph>(graph: &G, node: G::Node) -> HashSet<G::Node> {
+    graph.pre_order_iter_at(node).collect()
+}
+
+/**
+ * The nodes reachable from every node, including the node itself. Each
+ * component's set is built once from its successors in the condensation, so
+ * this is cheaper than a search per node.
+ */
+pub fn reachability<G: Graph>(graph: &G) -> HashMap<G::Node, HashSet<G::Node>> {
+    let scc = StronglyConnectedComponents::new(graph);
+    let mut reachable: Vec<HashSet<G::Node>> = Vec::with_capacity(scc.len());
+    // Reverse topological order, so successors are always done first
+    for idx in 0..scc.len() {
+        let mut nodes: HashSet<_> = scc.members(idx).iter().copied().collect();
+        for succ in scc.successors(idx) {
+            nodes.extend(reachable[succ].iter().copied());
+        }
+        reachable.push(nodes);
+    }
+    graph
+        .nodes()
+        .map(|node| (node, reachable[scc.component(node)].clone()))
+        .collect()
+}
+
+/**
+ * A breadth first search from a single node, recording the order nodes are
+ * reached in and the length of the shortest path to each of them.
+ */
+pub struct BreadthFirstSearch<N> {
+    order: Vec<N>,
+    distance: HashMap<N, usize>,
+    parent: HashMap<N, N>,
+}
+
+impl<N: Copy + Eq + Hash + Debug> BreadthFirstSearch<N> {
+    pub fn new<G: Graph<Node = N>>(graph: &G, start: N) -> Self {
+        let mut order = vec![start];
+        let mut distance = HashMap::from([(start, 0)]);
+        let mut parent = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            for succ in graph.successors(node) {
+                if distance.contains_key(&succ) {
+                    continue;
+                }
+                distance.insert(succ, distance[&node] + 1);
+                parent.insert(succ, node);
+                order.push(succ);
+                queue.push_back(succ);
+            }
+        }
+        BreadthFirstSearch {
+            order,
+            distance,
+            parent,
+        }
+    }
+
+    /**
+     * The reached nodes, in order of distance.
+     */
+    pub fn order(&self) -> &[N] {
+        &self.order
+    }
+
+    /**
+     * The number of edges on a shortest path to `node`, if it was reached.
+     */
+    pub fn distance(&self, node: N) -> Option<usize> {
+        self.distance.get(&node).copied()
+    }
+
+    pub fn parent(&self, node: N) -> Option<N> {
+        self.parent.get(&node).copied()
+    }
+
+    /**
+     * A shortest path from the start to `node`, including both ends.
+     */
+    pub fn path_to(&self, node: N) -> Option<Vec<N>> {
+        if !self.distance.contains_key(&node) {
+            return None;
+        }
+        let mut path = vec![node];
+        while let Some(parent) = self.parent(*path.last().unwrap()) {
+            path.push(parent);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /** An edge the search followed to a new node */
+    Tree,
+    /** An edge to a node still being searched, closing a cycle */
+    Back,
+    /** An edge to an already searched descendant */
+    Forward,
+    /** Any other edge, between unrelated subtrees */
+    Cross,
+}
+
+fn classify<G: Graph>(
+    graph: &G,
+    root: G::Node,
+    pre_order: &mut HashMap<G::Node, usize>,
+    finished: &mut HashSet<G::Node>,
+    edges: &mut Vec<(G::Node, G::Node, EdgeKind)>,
+) {
+    pre_order.insert(root, pre_order.len());
+    // Each entry is a node being searched and the successors it has left
+    let mut stack = vec![(root, graph.successors(root).collect::<Vec<_>>().into_iter())];
+    while let Some((node, succs)) = stack.last_mut() {
+        let node = *node;
+        match succs.next() {
+            Some(succ) => {
+                let kind = match pre_order.get(&succ) {
+                    None => EdgeKind::Tree,
+                    Some(_) if !finished.contains(&succ) => EdgeKind::Back,
+                    Some(order) if pre_order[&node] < *order => EdgeKind::Forward,
+                    Some(_) => EdgeKind::Cross,
+                };
+                edges.push((node, succ, kind));
+                if kind == EdgeKind::Tree {
+                    pre_order.insert(succ, pre_order.len());
+                    let succs: Vec<_> = graph.successors(succ).collect();
+                    stack.push((succ, succs.into_iter()));
+                }
+            }
+            None => {
+                stack.pop();
+                finished.insert(node);
+            }
+        }
+    }
+}
+
+/**
+ * Classifies every edge by a depth first search in the same order as
+ * `pre_order_iter`, started at the entry and then at every node not yet
+ * visited. Edges are returned in the order the search meets them.
+ */
+pub fn classify_edges<G: Graph>(graph: &G) -> Vec<(G::Node, G::Node, EdgeKind)> {
+    let mut pre_order = HashMap::new();
+    let mut finished = HashSet::new();
+    let mut edges = Vec::new();
+    for node in graph.entry_node().into_iter().chain(graph.nodes()) {
+        if !pre_order.contains_key(&node) {
+            classify(graph, node, &mut pre_order, &mut finished, &mut edges);
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::graph::tests::SimpleGraph;
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+
+    fn sorted(mut nodes: Vec<i32>) -> Vec<i32> {
+        nodes.sort();
+        nodes
+    }
+
+    #[test]
+    fn test_scc() {
+        // 0 -> 1 <-> 2 -> 3 -> 3, 2 -> 4 -> 5 -> 4
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..6);
+        graph.extend_edges(
+            vec![
+                (0, 1),
+                (1, 2),
+                (2, 1),
+                (2, 3),
+                (3, 3),
+                (2, 4),
+                (4, 5),
+                (5, 4),
+            ]
+            .into_iter(),
+        );
+        let scc = strongly_connected_components(&graph);
+        let components: Vec<_> = scc.components().iter().cloned().map(sorted).collect();
+        assert_eq!(components, vec![vec![3], vec![4, 5], vec![1, 2], vec![0]]);
+        assert!(!scc.is_cyclic(scc.component(0)));
+        assert!(scc.is_cyclic(scc.component(3)));
+        assert!(scc.is_cyclic(scc.component(4)));
+        assert_eq!(scc.component(1), scc.component(2));
+
+        let order: Vec<_> = scc.topological_order().collect();
+        assert_eq!(order, vec![3, 2, 1, 0]);
+        assert_eq!(scc.entry_node(), Some(3));
+        assert_eq!(scc.successors(2).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(scc.predecessors(0).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_scc_without_entry() {
+        // A call graph: main calls f and g, which call each other
+        let mut graph = SimpleGraph::new();
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (1, 2), (2, 1), (3, 3)].into_iter());
+        let scc = strongly_connected_components(&graph);
+        assert_eq!(scc.len(), 3);
+        assert_eq!(sorted(scc.members(0).to_vec()), vec![1, 2]);
+        assert_eq!(scc.members(1), &[0]);
+        assert_eq!(scc.members(2), &[3]);
+        assert!(scc.is_cyclic(2));
+        assert_eq!(scc.entry_node(), None);
+    }
+
+    #[test]
+    fn test_reachability() {
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..5);
+        graph.extend_edges(vec![(0, 1), (1, 2), (2, 1), (2, 3), (4, 3)].into_iter());
+        let reachability = reachability(&graph);
+        assert_eq!(reachability[&0], HashSet::from([0, 1, 2, 3]));
+        assert_eq!(reachability[&1], HashSet::from([1, 2, 3]));
+        assert_eq!(reachability[&3], HashSet::from([3]));
+        assert_eq!(reachability[&4], HashSet::from([3, 4]));
+        assert_eq!(reachable_from(&graph, 2), HashSet::from([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_bfs() {
+        // 0 -> (1 | 2), 1 -> 3, 2 -> 3, 3 -> 4, 0 -> 4
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..6);
+        graph.extend_edges(vec![(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (0, 4)].into_iter());
+        let bfs = BreadthFirstSearch::new(&graph, 0);
+        assert_eq!(bfs.order(), &[0, 1, 2, 4, 3]);
+        assert_eq!(bfs.distance(0), Some(0));
+        assert_eq!(bfs.distance(3), Some(2));
+        assert_eq!(bfs.distance(4), Some(1));
+        assert_eq!(bfs.distance(5), None);
+        assert_eq!(bfs.path_to(3), Some(vec![0, 1, 3]));
+        assert_eq!(bfs.path_to(5), None);
+    }
+
+    #[test]
+    fn test_classify_edges() {
+        // 0 -> 1 -> 2 -> 1, 0 -> 2, 0 -> 3 -> 2
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (1, 2), (2, 1), (0, 2), (0, 3), (3, 2)].into_iter());
+        let edges = classify_edges(&graph);
+        assert_eq!(
+            edges,
+            vec![
+                (0, 1, EdgeKind::Tree),
+                (1, 2, EdgeKind::Tree),
+                (2, 1, EdgeKind::Back),
+                (0, 2, EdgeKind::Forward),
+                (0, 3, EdgeKind::Tree),
+                (3, 2, EdgeKind::Cross),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // Deep enough to overflow the stack with one call per node
+        let len = 100_000;
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..len);
+        graph.extend_edges((1..len).map(|n| (n - 1, n)));
+        // Without an edge back to the start every node is its own component
+        let scc = strongly_connected_components(&graph);
+        assert_eq!(scc.len(), len as usize);
+        assert!(scc
+            .topological_order()
+            .map(|idx| scc.members(idx)[0])
+            .eq(0..len));
+
+        graph.add_edge(len - 1, 0);
+        graph.add_edge(len - 1, len - 1);
+        let scc = strongly_connected_components(&graph);
+        assert_eq!(scc.len(), 1);
+        assert_eq!(scc.members(0).len(), len as usize);
+
+        let edges = classify_edges(&graph);
+        assert_eq!(edges.len(), len as usize + 1);
+        assert!(edges[..len as usize - 1]
+            .iter()
+            .all(|(_, _, kind)| *kind == EdgeKind::Tree));
+        assert_eq!(
+            edges[len as usize - 1..],
+            [
+                (len - 1, 0, EdgeKind::Back),
+                (len - 1, len - 1, EdgeKind::Back)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_random_graphs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let mut graph = SimpleGraph::new();
+            graph.set_entry(0);
+            graph.extend_nodes(0..10);
+            let num_edges = Uniform::new(0, 25).sample(&mut rng);
+            for _ in 0..num_edges {
+                let src = Uniform::new(0, 10).sample(&mut rng);
+                let dst = Uniform::new(0, 10).sample(&mut rng);
+                graph.add_edge(src, dst);
+            }
+
+            // Two nodes share a component iff they reach each other
+            let scc = strongly_connected_components(&graph);
+            let reachability = reachability(&graph);
+            for a in 0..10 {
+                assert_eq!(reachability[&a], reachable_from(&graph, a));
+                for b in 0..10 {
+                    let mutual = reachability[&a].contains(&b) && reachability[&b].contains(&a);
+                    assert_eq!(scc.component(a) == scc.component(b), mutual);
+                }
+            }
+            let position: HashMap<_, _> = scc
+                .topological_order()
+                .enumerate()
+                .map(|(pos, idx)| (idx, pos))
+                .collect();
+            for idx in scc.nodes() {
+                for succ in scc.successors(idx) {
+                    assert!(position[&idx] < position[&succ]);
+                }
+            }
+
+            // Back edges are exactly the edges within a cycle that close it
+            let edges = classify_edges(&graph);
+            let num_edges: usize = graph.nodes().map(|n| graph.successors(n).count()).sum();
+            assert_eq!(edges.len(), num_edges);
+            for (from, to, kind) in edges {
+                if kind == EdgeKind::Back {
+                    assert_eq!(scc.component(from), scc.component(to));
+                }
+            }
+            let has_back_edge = classify_edges(&graph)
+                .iter()
+                .any(|(_, _, kind)| *kind == EdgeKind::Back);
+            assert_eq!(has_back_edge, (0..scc.len()).any(|idx| scc.is_cyclic(idx)));
+
+            let bfs = BreadthFirstSearch::new(&graph, 0);
+            for node in bfs.order() {
+                let path = bfs.path_to(*node).unwrap();
+                assert_eq!(path.len(), bfs.distance(*node).unwrap() + 1);
+                for pair in path.windows(2) {
+                    assert!(graph.successors(pair[0]).any(|s| s == pair[1]));
+                }
+            }
+            assert_eq!(
+                bfs.order().iter().copied().collect::<HashSet<_>>(),
+                reachability[&0]
+            );
+        }
+    }
+}
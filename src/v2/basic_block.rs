@@ -1,16 +1,33 @@
 use crate::v2::context::{Context, ContextRef};
 use crate::v2::error::{CompilerError, CompilerErrorType};
 use crate::v2::instruction::{Code, Instruction};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 
 pub struct BasicBlocks<'a, I: Instruction> {
     ctx: &'a Context,
-    blocks: HashMap<String, ContextRef<'a, BasicBlock<I>>>,
+    /** Indexed by `BlockId`, in insertion order. Removed blocks leave a `None` */
+    blocks: Vec<Option<ContextRef<'a, BasicBlock<I>>>>,
+    ids: HashMap<String, BlockId>,
     entry: Option<ContextRef<'a, BasicBlock<I>>>,
     exit: Option<ContextRef<'a, BasicBlock<I>>>,
     label_prefix: String,
     next_label_idx: usize,
+    /** Labels of the blocks inserted, replaced or removed, see `take_changes` */
+    changes: Vec<String>,
+}
+
+/**
+ * Identifies a block by the order it was inserted in. The id stays the same
+ * when the block is replaced and is never reused after it is removed.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(usize);
+
+impl BlockId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -49,11 +66,13 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
     pub fn new(ctx: &'a Context, label_prefix: String) -> Self {
         BasicBlocks {
             ctx,
-            blocks: HashMap::new(),
+            blocks: Vec::new(),
+            ids: HashMap::new(),
             entry: None,
             exit: None,
             label_prefix,
             next_label_idx: 0,
+            changes: Vec::new(),
         }
     }
 
@@ -65,17 +84,33 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
         self.exit
     }
 
+    /**
+     * The blocks in the order they were inserted. Replacing a block keeps its
+     * place.
+     */
     pub fn blocks(&self) -> impl Iterator<Item = ContextRef<'a, BasicBlock<I>>> + '_ {
-        self.blocks.values().cloned()
+        self.blocks.iter().flatten().cloned()
     }
 
     pub fn get(&self, label: &str) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
-        self.blocks
+        let id = self.id(label)?;
+        Ok(self.blocks[id.0].unwrap())
+    }
+
+    pub fn id(&self, label: &str) -> Result<BlockId, CompilerError> {
+        self.ids
             .get(label)
-            .cloned()
+            .copied()
             .ok_or(CompilerErrorType::MissingLabel.with_label(label.to_string()))
     }
 
+    /**
+     * The block with the given id, if it hasn't been removed.
+     */
+    pub fn block(&self, id: BlockId) -> Option<ContextRef<'a, BasicBlock<I>>> {
+        self.blocks.get(id.0).copied().flatten()
+    }
+
     /**
      * Inserts the block into the basic blocks, returning a reference to the block.
      * If the label already exists, returns an error.
@@ -86,15 +121,18 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
     ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
         let label = block.label.clone();
         let block = self.ctx.create(block);
-        if self.blocks.contains_key(&label) {
+        if self.ids.contains_key(&label) {
             return Err(CompilerErrorType::DuplicateLabel.with_label(label));
         }
-        self.blocks.insert(label, block);
+        self.record_change(&label);
+        self.ids.insert(label, BlockId(self.blocks.len()));
+        self.blocks.push(Some(block));
         Ok(block)
     }
 
     /**
      * Replaces the block with the same label in the basic blocks, returning a reference to the block.
+     * The entry and exit blocks are updated if they are the block being replaced.
      * If the label does not exist, returns an error.
      */
     pub fn replace_block(
@@ -103,10 +141,15 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
     ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
         let label = block.label.clone();
         let block = self.ctx.create(block);
-        if !self.blocks.contains_key(&label) {
-            return Err(CompilerErrorType::MissingLabel.with_label(label));
+        let id = self.id(&label)?;
+        if self.entry.is_some_and(|entry| entry.label() == &label) {
+            self.entry = Some(block);
+        }
+        if self.exit.is_some_and(|exit| exit.label() == &label) {
+            self.exit = Some(block);
         }
-        self.blocks.insert(label, block);
+        self.record_change(&label);
+        self.blocks[id.0] = Some(block);
         Ok(block)
     }
 
@@ -115,17 +158,13 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
      * If the label is not present in basic blocks, returns an error.
      */
     pub fn set_entry(&mut self, block: ContextRef<'a, BasicBlock<I>>) -> Result<(), CompilerError> {
-        if !self.blocks.contains_key(block.label()) {
-            return Err(CompilerErrorType::MissingLabel.with_label(block.label().clone()));
-        }
+        self.id(block.label())?;
         self.entry = Some(block);
         Ok(())
     }
 
     pub fn set_exit(&mut self, block: ContextRef<'a, BasicBlock<I>>) -> Result<(), CompilerError> {
-        if !self.blocks.contains_key(block.label()) {
-            return Err(CompilerErrorType::MissingLabel.with_label(block.label().clone()));
-        }
+        self.id(block.label())?;
         self.exit = Some(block);
         Ok(())
     }
@@ -137,11 +176,214 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
      */
     pub fn create_unique_label(&mut self) -> String {
         let label = format!("{}block{}", self.label_prefix, self.next_label_idx);
-        assert!(!self.blocks.contains_key(&label));
+        assert!(!self.ids.contains_key(&label));
         self.next_label_idx += 1;
         label
     }
 
+    /**
+     * Inserts a block that jumps to `header` and retargets the edges from
+     * `preds` to it, returning a reference to the new block. Phi labels in
+     * the header are retargeted as well, so with more than one predecessor
+     * the header must not have any phis, and if it does an error is returned.
+     * If the header was the entry block, the preheader becomes the entry.
+     */
+    pub fn insert_preheader(
+        &mut self,
+        header: &str,
+        preds: &[&str],
+    ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
+        let header_block = self.get(header)?;
+        // The phis would get one argument per predecessor, all from the preheader
+        if preds.len() > 1 && header_block.instrs.iter().any(|instr| instr.is_phi()) {
+            return Err(
+                CompilerErrorType::ControlFlowPreheaderWithPhis.with_label(header.to_string())
+            );
+        }
+        let label = self.create_unique_label();
+        let mut header_instrs = header_block.instrs.clone();
+        let terminator = header_instrs.pop().unwrap();
+        for pred in preds {
+            let pred_block = self.get(pred)?;
+            let mut instrs = pred_block.non_terminating_instrs().to_vec();
+            instrs.push(pred_block.terminator().clone().rename_label(header, &label));
+            self.replace_block(BasicBlock::new(pred.to_string(), instrs)?)?;
+            header_instrs = header_instrs
+                .into_iter()
+                .map(|instr| instr.rename_label(pred, &label))
+                .collect();
+        }
+        header_instrs.push(terminator);
+        self.replace_block(BasicBlock::new(header.to_string(), header_instrs)?)?;
+        let preheader = BasicBlock::new(label, vec![Instruction::jump(header.to_string())])?;
+        let preheader = self.insert_block(preheader)?;
+        if self.entry.is_some_and(|entry| entry.label() == header) {
+            self.entry = Some(preheader);
+        }
+        Ok(preheader)
+    }
+
+    /**
+     * Returns the labels of the blocks inserted, replaced or removed since the
+     * last call, in the order they were first changed. These are the blocks
+     * whose edges `ControlFlowEdges::update` needs to recompute.
+     */
+    pub fn take_changes(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /**
+     * Inserts a block on the edge from `from` to `to`, returning a reference
+     * to the new block. Every branch from `from` to `to` goes through the new
+     * block, and phis in `to` take their argument from it instead.
+     * If there is no such edge, returns an error.
+     */
+    pub fn split_edge(
+        &mut self,
+        from: &str,
+        to: &str,
+    ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
+        self.check_edge(from, to)?;
+        let label = self.create_unique_label();
+        let from_block = self.get(from)?;
+        let mut instrs = from_block.non_terminating_instrs().to_vec();
+        instrs.push(from_block.terminator().clone().rename_label(to, &label));
+        self.replace_block(BasicBlock::new(from.to_string(), instrs)?)?;
+
+        // Only the phis are renamed, `to` may itself branch back to `from`
+        let to_block = self.get(to)?;
+        let mut instrs: Vec<_> = to_block
+            .non_terminating_instrs()
+            .iter()
+            .map(|instr| instr.clone().rename_label(from, &label))
+            .collect();
+        instrs.push(to_block.terminator().clone());
+        self.replace_block(BasicBlock::new(to.to_string(), instrs)?)?;
+        self.insert_block(BasicBlock::new(
+            label,
+            vec![Instruction::jump(to.to_string())],
+        )?)
+    }
+
+    /**
+     * Points the edge from `from` to `to` at `new_to` instead, returning a
+     * reference to the updated `from` block. Phis in `to` lose their argument
+     * from `from`, and phis in `new_to` are left for the caller to extend.
+     * If there is no such edge or `new_to` is missing, returns an error.
+     */
+    pub fn redirect_edge(
+        &mut self,
+        from: &str,
+        to: &str,
+        new_to: &str,
+    ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
+        self.check_edge(from, to)?;
+        self.get(new_to)?;
+        let from_block = self.get(from)?;
+        let mut instrs = from_block.non_terminating_instrs().to_vec();
+        instrs.push(from_block.terminator().clone().rename_label(to, new_to));
+        let from_block = self.replace_block(BasicBlock::new(from.to_string(), instrs)?)?;
+        self.remove_phi_labels(to, from)?;
+        Ok(from_block)
+    }
+
+    /**
+     * Removes the block with the given label, returning a reference to it.
+     * Branches to it become jumps to their other target, and phis in its
+     * successors lose their argument from it. Returns an error if the block is
+     * missing, is the entry or exit, or is the only successor of another
+     * block, in which case nothing is changed.
+     */
+    pub fn remove_block(
+        &mut self,
+        label: &str,
+    ) -> Result<ContextRef<'a, BasicBlock<I>>, CompilerError> {
+        let block = self.get(label)?;
+        let is_label = |other: Option<ContextRef<'a, BasicBlock<I>>>| {
+            other.is_some_and(|other| other.label() == label)
+        };
+        if is_label(self.entry) || is_label(self.exit) {
+            return Err(
+                CompilerErrorType::ControlFlowRemoveEntryOrExitBlock.with_label(label.to_string())
+            );
+        }
+        let mut terminators = Vec::new();
+        for pred in self.blocks() {
+            if pred.label() == label || !pred.successors().iter().any(|succ| succ == label) {
+                continue;
+            }
+            let targets: Vec<_> = pred
+                .successors()
+                .iter()
+                .filter(|succ| *succ != label)
+                .cloned()
+                .collect();
+            match targets.first() {
+                Some(target) => terminators.push((pred, Instruction::jump(target.clone()))),
+                None => {
+                    return Err(CompilerErrorType::ControlFlowRemoveOnlySuccessor
+                        .with_label(label.to_string())
+                        .with_block(pred.label().clone()))
+                }
+            }
+        }
+        for (pred, terminator) in terminators {
+            let mut instrs = pred.non_terminating_instrs().to_vec();
+            instrs.push(terminator);
+            self.replace_block(BasicBlock::new(pred.label().clone(), instrs)?)?;
+        }
+        for succ in block.successors() {
+            if succ != label {
+                self.remove_phi_labels(succ, label)?;
+            }
+        }
+        self.record_change(label);
+        if let Some(id) = self.ids.remove(label) {
+            self.blocks[id.0] = None;
+        }
+        Ok(block)
+    }
+
+    /**
+     * Splits every critical edge, an edge from a block with several
+     * successors to a block with several predecessors, so that code can be
+     * placed on the edge. Returns the new blocks, ordered by the labels of the
+     * edges they split.
+     */
+    pub fn split_critical_edges(
+        &mut self,
+    ) -> Result<Vec<ContextRef<'a, BasicBlock<I>>>, CompilerError> {
+        let mut num_preds: HashMap<&String, usize> = HashMap::new();
+        for block in self.blocks.iter().flatten() {
+            for succ in Self::distinct_successors(block) {
+                *num_preds.entry(succ).or_default() += 1;
+            }
+        }
+        let mut edges = Vec::new();
+        for block in self.blocks.iter().flatten() {
+            let succs = Self::distinct_successors(block);
+            if succs.len() < 2 {
+                continue;
+            }
+            for succ in succs {
+                if num_preds[succ] > 1 {
+                    edges.push((block.label().clone(), succ.clone()));
+                }
+            }
+        }
+        edges.sort();
+        edges
+            .iter()
+            .map(|(from, to)| self.split_edge(from, to))
+            .collect()
+    }
+
+    /**
+     * Builds the basic blocks of a function body. A synthetic entry block
+     * jumps to the first block, and both `ret` without a value and falling
+     * off the end of the body jump to a synthetic exit block. A `ret` with a
+     * value stays the terminator of its block.
+     */
     pub fn from_code(ctx: &'a Context, code: &[Code<I>]) -> Result<Self, CompilerError> {
         let mut blocks = BasicBlocks::new(ctx, Self::label_prefix(code));
 
@@ -151,7 +393,8 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
             if blocks.entry.is_none() {
                 let entry_label = blocks.create_unique_label() + "_entry";
                 let entry_block = BasicBlock::new(entry_label, vec![Instruction::jump(label)])?;
-                blocks.insert_block(entry_block)?;
+                let entry_block = blocks.insert_block(entry_block)?;
+                blocks.set_entry(entry_block)?;
             }
             blocks.insert_block(block)
         };
@@ -177,7 +420,7 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
                         curr_block = Some(BasicBlock::from_label(blocks.create_unique_label()));
                     }
                     let instrs = &mut curr_block.as_mut().unwrap().instrs;
-                    if instr.is_return() {
+                    if instr.is_return() && instr.args().is_empty() {
                         instrs.push(Instruction::jump(exit_block.label().clone()));
                     } else {
                         instrs.push(instr.clone());
@@ -191,7 +434,9 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
         }
         if let Some(mut block) = curr_block {
             assert!(!block.is_terminated());
-            block.instrs.push(Instruction::ret());
+            block
+                .instrs
+                .push(Instruction::jump(exit_block.label().clone()));
             insert_block(&mut blocks, block)?;
         }
         let exit_block = insert_block(&mut blocks, exit_block)?;
@@ -199,18 +444,64 @@ impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
         Ok(blocks)
     }
 
-    // pub fn to_code(&self) -> Result<Vec<Code>, CompilerError> {
-    //     let mut code = Vec::new();
-    //     let cfg = ControlFlowGraph::new(self)?;
-    //     // TODO: Do we want to try to clean up inserted entry/exit labels?
-    //     // TODO: Do we want to try to clean up fallthrough jumps?
-    //     // TODO: Do we want to try to clean up inserted unused labels?
-    //     // TODO: Do we want to preserve order of original labels?
-    //     for block in cfg.pre_order_iter() {
-    //         code.extend(block.code());
-    //     }
-    //     Ok(code)
-    // }
+    /**
+     * Converts the basic blocks back into a function body. The entry block
+     * comes first and the rest follow in the order they were inserted, so
+     * the original labels keep their order. Jumps to a synthetic exit block
+     * that only returns become `ret`, and jumps to the next block, a final
+     * `ret` without a value and synthetic labels nothing refers to are
+     * dropped. The result builds the same blocks again with `from_code`.
+     */
+    pub fn to_code(&self) -> Vec<Code<I>> {
+        let exit = self.exit.filter(|exit| {
+            self.is_synthetic(exit.label())
+                && exit.instrs().len() == 1
+                && exit.terminator().is_return()
+                && exit.terminator().args().is_empty()
+        });
+        let rest = self
+            .blocks()
+            .filter(|block| Some(*block) != self.entry && Some(*block) != exit);
+        let layout: Vec<_> = self.entry.into_iter().chain(rest).collect();
+
+        let exit_label = exit.as_ref().map(|exit| exit.label());
+        let mut terminators = Vec::with_capacity(layout.len());
+        for (idx, block) in layout.iter().enumerate() {
+            let mut terminator = block.terminator().clone();
+            if terminator.is_jump() && terminator.labels().first() == exit_label {
+                terminator = Instruction::ret();
+            }
+            let falls_through = match layout.get(idx + 1) {
+                Some(next) => terminator.is_jump() && terminator.labels()[0] == *next.label(),
+                None => terminator.is_return() && terminator.args().is_empty(),
+            };
+            terminators.push(Some(terminator).filter(|_| !falls_through));
+        }
+
+        let used: HashSet<_> = layout
+            .iter()
+            .flat_map(|block| block.non_terminating_instrs())
+            .chain(terminators.iter().flatten())
+            .flat_map(|instr| instr.labels().iter().cloned())
+            .collect();
+        let mut code = Vec::new();
+        for (block, terminator) in layout.iter().zip(terminators) {
+            if !self.is_synthetic(block.label()) || used.contains(block.label()) {
+                code.push(Code::Label {
+                    label: block.label().clone(),
+                });
+            }
+            code.extend(
+                block
+                    .non_terminating_instrs()
+                    .iter()
+                    .cloned()
+                    .chain(terminator)
+                    .map(Code::Instruction),
+            );
+        }
+        code
+    }
 }
 
 impl<I: Instruction> BasicBlock<I> {
@@ -248,16 +539,16 @@ impl<I: Instruction> BasicBlock<I> {
         self.terminator().labels()
     }
 
-    // pub fn code(&self) -> impl Iterator<Item = Code> + '_ {
-    //     let label = Code::Label {
-    //         label: self.label.clone(),
-    //     };
-    //     let instrs = self
-    //         .instrs
-    //         .iter()
-    //         .map(|inst| Code::Instruction(inst.clone()));
-    //     std::iter::once(label).chain(instrs)
-    // }
+    pub fn code(&self) -> impl Iterator<Item = Code<I>> + '_ {
+        let label = Code::Label {
+            label: self.label.clone(),
+        };
+        let instrs = self
+            .instrs
+            .iter()
+            .map(|inst| Code::Instruction(inst.clone()));
+        std::iter::once(label).chain(instrs)
+    }
 }
 
 impl<I: Instruction> BasicBlock<I> {
@@ -289,7 +580,57 @@ impl<I: Instruction> BasicBlock<I> {
     }
 }
 
+impl<'a, I: 'static + Instruction> BasicBlocks<'a, I> {
+    fn record_change(&mut self, label: &str) {
+        if !self.changes.iter().any(|change| change == label) {
+            self.changes.push(label.to_string());
+        }
+    }
+
+    fn check_edge(&self, from: &str, to: &str) -> Result<(), CompilerError> {
+        self.get(to)?;
+        if !self.get(from)?.successors().iter().any(|succ| succ == to) {
+            return Err(CompilerErrorType::ControlFlowMissingEdge
+                .with_label(to.to_string())
+                .with_block(from.to_string()));
+        }
+        Ok(())
+    }
+
+    fn distinct_successors(block: &BasicBlock<I>) -> Vec<&String> {
+        let mut succs: Vec<_> = block.successors().iter().collect();
+        succs.sort();
+        succs.dedup();
+        succs
+    }
+
+    /**
+     * Drops the arguments from `pred` of the phis in `label`.
+     */
+    fn remove_phi_labels(&mut self, label: &str, pred: &str) -> Result<(), CompilerError> {
+        let block = self.get(label)?;
+        if !block.instrs.iter().any(|instr| instr.is_phi()) {
+            return Ok(());
+        }
+        let instrs = block
+            .instrs
+            .iter()
+            .map(|instr| instr.clone().remove_phi_label(pred))
+            .collect();
+        self.replace_block(BasicBlock::new(label.to_string(), instrs)?)?;
+        Ok(())
+    }
+}
+
 impl<'a, I: Instruction> BasicBlocks<'a, I> {
+    /**
+     * Whether the label was made by `create_unique_label` rather than taken
+     * from the code.
+     */
+    fn is_synthetic(&self, label: &str) -> bool {
+        label.starts_with(&self.label_prefix)
+    }
+
     fn label_prefix(code: &[Code<I>]) -> String {
         let max = code
             .iter()
@@ -305,4 +646,425 @@ impl<'a, I: Instruction> BasicBlocks<'a, I> {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::bril::{EffectOps, Type, ValueOps};
+    use crate::v2::instruction::BrilInstruction;
+
+    fn branch(cond: &str, t: &str, f: &str) -> BrilInstruction {
+        BrilInstruction::effect(
+            EffectOps::Branch,
+            vec![cond.to_string()],
+            Vec::new(),
+            vec![t.to_string(), f.to_string()],
+        )
+    }
+
+    fn block(label: &str, instrs: Vec<BrilInstruction>) -> BasicBlock<BrilInstruction> {
+        BasicBlock::new(label.to_string(), instrs).unwrap()
+    }
+
+    #[test]
+    fn test_insert_preheader() {
+        // entry -> header <-> body, header -> exit
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let phi = BrilInstruction::value(
+            ValueOps::Phi,
+            Type::Int,
+            "i".to_string(),
+            vec!["zero".to_string(), "next".to_string()],
+            Vec::new(),
+            vec!["entry".to_string(), "body".to_string()],
+        );
+        let entry = bbs
+            .insert_block(block("entry", vec![BrilInstruction::jump("header".to_string())]))
+            .unwrap();
+        bbs.insert_block(block("header", vec![phi, branch("c", "body", "exit")]))
+            .unwrap();
+        bbs.insert_block(block("body", vec![BrilInstruction::jump("header".to_string())]))
+            .unwrap();
+        let exit = bbs
+            .insert_block(block("exit", vec![BrilInstruction::ret()]))
+            .unwrap();
+        bbs.set_entry(entry).unwrap();
+        bbs.set_exit(exit).unwrap();
+
+        let preheader = bbs.insert_preheader("header", &["entry"]).unwrap();
+        assert_eq!(preheader.label(), "_block0");
+        assert_eq!(preheader.successors(), &["header".to_string()]);
+        let entry = bbs.get("entry").unwrap();
+        assert_eq!(entry.successors(), &["_block0".to_string()]);
+        assert_eq!(bbs.entry().unwrap().successors(), entry.successors());
+        let header = bbs.get("header").unwrap();
+        assert_eq!(header.instrs()[0].labels(), &["_block0", "body"]);
+        assert_eq!(header.successors(), &["body", "exit"]);
+        assert_eq!(bbs.get("body").unwrap().successors(), &["header"]);
+    }
+
+    #[test]
+    fn test_insert_preheader_with_phis() {
+        // (left | right) -> header <-> body, with a phi in header
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let entry = bbs
+            .insert_block(block("entry", vec![branch("c", "left", "right")]))
+            .unwrap();
+        bbs.insert_block(block(
+            "left",
+            vec![BrilInstruction::jump("header".to_string())],
+        ))
+        .unwrap();
+        bbs.insert_block(block(
+            "right",
+            vec![BrilInstruction::jump("header".to_string())],
+        ))
+        .unwrap();
+        let phi = phi("i", &["a", "b", "next"], &["left", "right", "body"]);
+        bbs.insert_block(block("header", vec![phi, branch("c", "body", "exit")]))
+            .unwrap();
+        bbs.insert_block(block(
+            "body",
+            vec![BrilInstruction::jump("header".to_string())],
+        ))
+        .unwrap();
+        let exit = bbs
+            .insert_block(block("exit", vec![BrilInstruction::ret()]))
+            .unwrap();
+        bbs.set_entry(entry).unwrap();
+        bbs.set_exit(exit).unwrap();
+        bbs.take_changes();
+
+        assert_eq!(
+            bbs.insert_preheader("header", &["left", "right"])
+                .unwrap_err(),
+            CompilerErrorType::ControlFlowPreheaderWithPhis.with_label("header".to_string())
+        );
+        assert_eq!(bbs.get("left").unwrap().successors(), &["header"]);
+        assert!(bbs.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_insert_preheader_at_entry() {
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let header = bbs
+            .insert_block(block("header", vec![branch("c", "header", "exit")]))
+            .unwrap();
+        let exit = bbs
+            .insert_block(block("exit", vec![BrilInstruction::ret()]))
+            .unwrap();
+        bbs.set_entry(header).unwrap();
+        bbs.set_exit(exit).unwrap();
+
+        let preheader = bbs.insert_preheader("header", &[]).unwrap();
+        assert_eq!(bbs.entry().unwrap().label(), preheader.label());
+        assert_eq!(bbs.get("header").unwrap().successors(), &["header", "exit"]);
+        assert!(bbs.insert_preheader("missing", &[]).is_err());
+    }
+
+    fn phi(dest: &str, args: &[&str], labels: &[&str]) -> BrilInstruction {
+        BrilInstruction::value(
+            ValueOps::Phi,
+            Type::Int,
+            dest.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+            Vec::new(),
+            labels.iter().map(|label| label.to_string()).collect(),
+        )
+    }
+
+    /**
+     * entry -> (left | join), left -> join, with a phi in join
+     */
+    fn diamond(ctx: &Context) -> BasicBlocks<'_, BrilInstruction> {
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(ctx, "_".to_string());
+        let entry = bbs
+            .insert_block(block("entry", vec![branch("c", "left", "join")]))
+            .unwrap();
+        bbs.insert_block(block(
+            "left",
+            vec![BrilInstruction::jump("join".to_string())],
+        ))
+        .unwrap();
+        let join = bbs
+            .insert_block(block(
+                "join",
+                vec![
+                    phi("v", &["zero", "one"], &["entry", "left"]),
+                    BrilInstruction::ret(),
+                ],
+            ))
+            .unwrap();
+        bbs.set_entry(entry).unwrap();
+        bbs.set_exit(join).unwrap();
+        bbs
+    }
+
+    #[test]
+    fn test_split_edge() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        bbs.take_changes();
+        let split = bbs.split_edge("entry", "join").unwrap();
+        assert_eq!(split.label(), "_block0");
+        assert_eq!(split.successors(), &["join"]);
+        assert_eq!(bbs.get("entry").unwrap().successors(), &["left", "_block0"]);
+        assert_eq!(bbs.entry().unwrap().successors(), &["left", "_block0"]);
+        let join = bbs.get("join").unwrap();
+        assert_eq!(join.instrs()[0].labels(), &["_block0", "left"]);
+        assert_eq!(join.instrs()[0].args(), &["zero", "one"]);
+        assert_eq!(bbs.take_changes(), vec!["entry", "join", "_block0"]);
+
+        assert_eq!(
+            bbs.split_edge("left", "entry").unwrap_err(),
+            CompilerErrorType::ControlFlowMissingEdge
+                .with_label("entry".to_string())
+                .with_block("left".to_string())
+        );
+        assert!(bbs.split_edge("left", "missing").is_err());
+    }
+
+    #[test]
+    fn test_split_self_loop() {
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let header = bbs
+            .insert_block(block(
+                "header",
+                vec![
+                    phi("i", &["zero", "next"], &["entry", "header"]),
+                    branch("c", "header", "exit"),
+                ],
+            ))
+            .unwrap();
+        bbs.set_entry(header).unwrap();
+        bbs.split_edge("header", "header").unwrap();
+        let header = bbs.get("header").unwrap();
+        assert_eq!(header.successors(), &["_block0", "exit"]);
+        assert_eq!(header.instrs()[0].labels(), &["entry", "_block0"]);
+        assert_eq!(bbs.get("_block0").unwrap().successors(), &["header"]);
+    }
+
+    #[test]
+    fn test_redirect_edge() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        let entry = bbs.redirect_edge("entry", "join", "left").unwrap();
+        assert_eq!(entry.successors(), &["left", "left"]);
+        let join = bbs.get("join").unwrap();
+        assert_eq!(join.instrs()[0].labels(), &["left"]);
+        assert_eq!(join.instrs()[0].args(), &["one"]);
+        assert!(bbs.redirect_edge("entry", "join", "left").is_err());
+        assert!(bbs.redirect_edge("entry", "left", "missing").is_err());
+    }
+
+    #[test]
+    fn test_remove_block() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        assert_eq!(
+            bbs.remove_block("join").unwrap_err(),
+            CompilerErrorType::ControlFlowRemoveEntryOrExitBlock.with_label("join".to_string())
+        );
+        bbs.take_changes();
+        let left = bbs.remove_block("left").unwrap();
+        assert_eq!(left.label(), "left");
+        assert!(bbs.get("left").is_err());
+        assert_eq!(bbs.blocks().count(), 2);
+        let entry = bbs.get("entry").unwrap();
+        assert_eq!(entry.instrs().len(), 1);
+        assert_eq!(entry.successors(), &["join"]);
+        let join = bbs.get("join").unwrap();
+        assert_eq!(join.instrs()[0].labels(), &["entry"]);
+        assert_eq!(join.instrs()[0].args(), &["zero"]);
+        assert_eq!(bbs.take_changes(), vec!["entry", "join", "left"]);
+    }
+
+    #[test]
+    fn test_remove_only_successor() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        bbs.redirect_edge("entry", "join", "left").unwrap();
+        bbs.take_changes();
+        assert_eq!(
+            bbs.remove_block("left").unwrap_err(),
+            CompilerErrorType::ControlFlowRemoveOnlySuccessor
+                .with_label("left".to_string())
+                .with_block("entry".to_string())
+        );
+        assert!(bbs.get("left").is_ok());
+        assert!(bbs.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_split_critical_edges() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        let split = bbs.split_critical_edges().unwrap();
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].successors(), &["join"]);
+        assert_eq!(
+            bbs.get("entry").unwrap().successors(),
+            &["left", split[0].label().as_str()]
+        );
+        assert!(bbs.split_critical_edges().unwrap().is_empty());
+
+        // Both edges of a branch to the same block count as one
+        let mut bbs = diamond(&ctx);
+        bbs.redirect_edge("entry", "join", "left").unwrap();
+        assert!(bbs.split_critical_edges().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_block_order() {
+        let ctx = Context::new();
+        let mut bbs = diamond(&ctx);
+        let labels = |bbs: &BasicBlocks<BrilInstruction>| {
+            bbs.blocks()
+                .map(|block| block.label().clone())
+                .collect::<Vec<_>>()
+        };
+        let order = labels(&bbs);
+        let ids: Vec<_> = order.iter().map(|label| bbs.id(label).unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Replacing keeps the place and the id
+        let left = bbs.get("left").unwrap();
+        bbs.replace_block(BasicBlock::clone(&left)).unwrap();
+        assert_eq!(labels(&bbs), order);
+        assert_eq!(bbs.id("left").unwrap(), ids[1]);
+
+        // Splitting appends, removing leaves the other ids alone
+        let split = bbs.split_edge("entry", "join").unwrap();
+        assert_eq!(labels(&bbs).last(), Some(split.label()));
+        bbs.remove_block(split.label()).unwrap();
+        assert_eq!(labels(&bbs), order);
+        let split_id = BlockId(ids.len());
+        assert!(bbs.block(split_id).is_none());
+        for (label, id) in order.iter().zip(ids) {
+            assert_eq!(bbs.block(id).unwrap().label(), label);
+        }
+
+        // A label inserted again gets a new id
+        bbs.insert_block(block(
+            split.label(),
+            vec![BrilInstruction::jump("join".to_string())],
+        ))
+        .unwrap();
+        assert!(bbs.id(split.label()).unwrap() > split_id);
+    }
+
+    fn label(label: &str) -> Code<BrilInstruction> {
+        Code::Label {
+            label: label.to_string(),
+        }
+    }
+
+    fn op(op: ValueOps, t: Type, dest: &str, args: &[&str]) -> Code<BrilInstruction> {
+        Code::Instruction(BrilInstruction::value(
+            op,
+            t,
+            dest.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+            Vec::new(),
+            Vec::new(),
+        ))
+    }
+
+    fn effect(op: EffectOps, args: &[&str], labels: &[&str]) -> Code<BrilInstruction> {
+        Code::Instruction(BrilInstruction::effect(
+            op,
+            args.iter().map(|arg| arg.to_string()).collect(),
+            Vec::new(),
+            labels.iter().map(|label| label.to_string()).collect(),
+        ))
+    }
+
+    fn render(code: &[Code<BrilInstruction>]) -> Vec<String> {
+        code.iter()
+            .map(|c| match c.clone().into() {
+                crate::bril::Code::Label { label } => format!(".{}:", label),
+                crate::bril::Code::Instruction(instr) => format!("  {}", instr),
+            })
+            .collect()
+    }
+
+    fn loop_code() -> Vec<Code<BrilInstruction>> {
+        vec![
+            Code::Instruction(BrilInstruction::const_int("v".to_string(), 0)),
+            label("loop"),
+            op(ValueOps::Lt, Type::Bool, "c", &["v", "n"]),
+            effect(EffectOps::Branch, &["c"], &["body", "done"]),
+            label("body"),
+            op(ValueOps::Add, Type::Int, "v", &["v", "one"]),
+            effect(EffectOps::Jump, &[], &["loop"]),
+            label("done"),
+            effect(EffectOps::Branch, &["c"], &["early", "late"]),
+            label("early"),
+            effect(EffectOps::Return, &["v"], &[]),
+            label("late"),
+            effect(EffectOps::Print, &["v"], &[]),
+            effect(EffectOps::Return, &[], &[]),
+        ]
+    }
+
+    #[test]
+    fn test_from_code() {
+        let ctx = Context::new();
+        let bbs = BasicBlocks::from_code(&ctx, &loop_code()).unwrap();
+        let entry = bbs.entry().unwrap();
+        let exit = bbs.exit().unwrap();
+        assert_eq!(entry.label(), "_block2_entry");
+        assert_eq!(entry.successors(), &["_block1"]);
+        assert_eq!(exit.label(), "_block0_exit");
+        assert_eq!(bbs.get("_block1").unwrap().successors(), &["loop"]);
+        assert_eq!(bbs.get("late").unwrap().successors(), &["_block0_exit"]);
+        // Returning a value keeps the return instead of jumping to the exit
+        assert!(bbs.get("early").unwrap().successors().is_empty());
+        assert_eq!(bbs.blocks().count(), 8);
+    }
+
+    #[test]
+    fn test_to_code() {
+        let ctx = Context::new();
+        let code = loop_code();
+        let bbs = BasicBlocks::from_code(&ctx, &code).unwrap();
+        let result = bbs.to_code();
+        // Only the final return is gone
+        assert_eq!(render(&result), render(&code[..code.len() - 1]));
+
+        let again = BasicBlocks::from_code(&ctx, &result).unwrap();
+        assert_eq!(render(&again.to_code()), render(&result));
+    }
+
+    #[test]
+    fn test_to_code_synthetic_labels() {
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::from_code(&ctx, &loop_code()).unwrap();
+        let split = bbs.split_edge("loop", "done").unwrap();
+        let result = bbs.to_code();
+        assert_eq!(
+            render(&result)[3..],
+            [
+                "  br c .body ._block3;",
+                ".body:",
+                "  v: int = add v one;",
+                "  jmp .loop;",
+                ".done:",
+                "  br c .early .late;",
+                ".early:",
+                "  ret v;",
+                ".late:",
+                "  print v;",
+                "  ret;",
+                "._block3:",
+                "  jmp .done;",
+            ]
+        );
+        assert_eq!(split.label(), "_block3");
+
+        let again = BasicBlocks::from_code(&ctx, &result).unwrap();
+        assert_eq!(render(&again.to_code()), render(&result));
+    }
+}
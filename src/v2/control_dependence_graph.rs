@@ -0,0 +1,184 @@
+use super::graph::Graph;
+use super::post_dominance_tree::PostDominanceTree;
+use std::collections::HashMap;
+
+/**
+ * A node is control dependent on a branch if one successor of the branch
+ * always leads to the node while another may avoid it, i.e. the branch is in
+ * the node's post-dominance frontier. As a graph, the predecessors of a node
+ * are the branches it depends on and the successors of a branch are the nodes
+ * depending on it. Nodes that can't reach the exit have no dependencies,
+ * and branches into them aren't counted as decisions.
+ */
+pub struct ControlDependenceGraph<'a, G: Graph> {
+    post_dominance_tree: PostDominanceTree<'a, G>,
+    /** The branches each node depends on */
+    dependencies: HashMap<G::Node, Vec<G::Node>>,
+    /** The nodes depending on each branch */
+    dependents: HashMap<G::Node, Vec<G::Node>>,
+    nodes: Vec<G::Node>,
+    entry: Option<G::Node>,
+}
+
+impl<'a, G: Graph> ControlDependenceGraph<'a, G> {
+    pub fn new(graph: &'a G) -> Self {
+        let post_dominance_tree = PostDominanceTree::new(graph);
+        let nodes: Vec<_> = graph.nodes().collect();
+        let mut dependencies = HashMap::new();
+        let mut dependents: HashMap<G::Node, Vec<G::Node>> = HashMap::new();
+        for node in nodes.iter() {
+            // Keep the branches in node order so that iteration is deterministic
+            let frontier: Vec<_> = post_dominance_tree.post_dominance_frontier(*node).collect();
+            let branches: Vec<_> = nodes
+                .iter()
+                .filter(|branch| frontier.contains(branch))
+                .copied()
+                .collect();
+            for branch in branches.iter() {
+                dependents.entry(*branch).or_default().push(*node);
+            }
+            dependencies.insert(*node, branches);
+        }
+        ControlDependenceGraph {
+            post_dominance_tree,
+            dependencies,
+            dependents,
+            nodes,
+            entry: graph.entry_node(),
+        }
+    }
+
+    /** The branches that decide whether `node` executes */
+    pub fn dependencies(&self, node: G::Node) -> impl Iterator<Item = G::Node> + '_ {
+        self.dependencies.get(&node).into_iter().flatten().copied()
+    }
+
+    /** The nodes whose execution `branch` decides */
+    pub fn dependents(&self, branch: G::Node) -> impl Iterator<Item = G::Node> + '_ {
+        self.dependents.get(&branch).into_iter().flatten().copied()
+    }
+
+    pub fn is_control_dependent(&self, node: G::Node, branch: G::Node) -> bool {
+        self.dependencies(node).any(|dependency| dependency == branch)
+    }
+
+    pub fn post_dominance_tree(&self) -> &PostDominanceTree<'a, G> {
+        &self.post_dominance_tree
+    }
+}
+
+impl<'a, G: Graph> Graph for ControlDependenceGraph<'a, G> {
+    type Node = G::Node;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        self.entry
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        // Dependencies don't flow towards a single node
+        None
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.dependencies(node)
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.dependents(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::graph::tests::SimpleGraph;
+
+    fn dependencies<G: Graph>(cdg: &ControlDependenceGraph<G>, node: G::Node) -> Vec<G::Node> {
+        cdg.dependencies(node).collect()
+    }
+
+    #[test]
+    fn test_if_else() {
+        // 0 -> 1 -> (2 | 3) -> 4 -> 5
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.set_exit(5);
+        graph.extend_nodes(0..6);
+        graph.extend_edges(vec![(0, 1), (1, 2), (1, 3), (2, 4), (3, 4), (4, 5)].into_iter());
+        let cdg = ControlDependenceGraph::new(&graph);
+
+        assert_eq!(dependencies(&cdg, 2), vec![1]);
+        assert_eq!(dependencies(&cdg, 3), vec![1]);
+        for node in [0, 1, 4, 5] {
+            assert!(dependencies(&cdg, node).is_empty());
+        }
+        assert_eq!(cdg.dependents(1).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(cdg.is_control_dependent(2, 1));
+        assert!(!cdg.is_control_dependent(4, 1));
+    }
+
+    #[test]
+    fn test_loops() {
+        // The textbook example from Ferrante, Ottenstein and Warren, with a
+        // while loop (1, 2) around an if (3 | 4) and a nested do-while on 5
+        //
+        // 0 -> 1 -> (2 | 7), 2 -> (3 | 4), 3 -> 5, 4 -> 5, 5 -> (5 | 6),
+        // 6 -> 1, 7 -> 8
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.set_exit(8);
+        graph.extend_nodes(0..9);
+        graph.extend_edges(
+            vec![
+                (0, 1),
+                (1, 2),
+                (1, 7),
+                (2, 3),
+                (2, 4),
+                (3, 5),
+                (4, 5),
+                (5, 5),
+                (5, 6),
+                (6, 1),
+                (7, 8),
+            ]
+            .into_iter(),
+        );
+        let cdg = ControlDependenceGraph::new(&graph);
+
+        assert!(dependencies(&cdg, 0).is_empty());
+        assert_eq!(dependencies(&cdg, 1), vec![1]);
+        assert_eq!(dependencies(&cdg, 2), vec![1]);
+        assert_eq!(dependencies(&cdg, 3), vec![2]);
+        assert_eq!(dependencies(&cdg, 4), vec![2]);
+        assert_eq!(dependencies(&cdg, 5), vec![1, 5]);
+        assert_eq!(dependencies(&cdg, 6), vec![1]);
+        assert!(dependencies(&cdg, 7).is_empty());
+        assert!(dependencies(&cdg, 8).is_empty());
+        assert_eq!(cdg.dependents(1).collect::<Vec<_>>(), vec![1, 2, 5, 6]);
+        assert_eq!(cdg.successors(5).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(cdg.predecessors(4).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        // 2 never reaches the exit, so nothing post-dominates it and the path
+        // from 0 into it is ignored: 1 still post-dominates 0
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.set_exit(3);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (0, 2), (2, 2), (1, 3)].into_iter());
+        let cdg = ControlDependenceGraph::new(&graph);
+
+        for node in 0..4 {
+            assert!(dependencies(&cdg, node).is_empty());
+        }
+        assert!(cdg.post_dominance_tree().post_dominates(1, 0));
+        assert!(cdg.post_dominance_tree().immediate_post_dominator(2).is_none());
+    }
+}
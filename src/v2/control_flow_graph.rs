@@ -15,6 +15,16 @@ pub struct ControlFlowGraph<'a, 'b, I: Instruction> {
     blocks: &'a BasicBlocks<'b, I>,
 }
 
+/**
+ * The edges of a control flow graph without the borrow of its blocks, so that
+ * the blocks can be changed and the graph brought up to date afterwards
+ * without recomputing every edge.
+ */
+pub struct ControlFlowEdges<'b, I: Instruction> {
+    pred: HashMap<String, Vec<ContextRef<'b, BasicBlock<I>>>>,
+    succ: HashMap<String, Vec<ContextRef<'b, BasicBlock<I>>>>,
+}
+
 impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
     pub fn new(blocks: &'a BasicBlocks<'b, I>) -> Result<Self, CompilerError> {
         let succ: HashMap<_, Vec<_>> = blocks
@@ -37,7 +47,14 @@ impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
                     .push(block);
             }
         }
+        Self::from_edges(blocks, pred, succ)
+    }
 
+    fn from_edges(
+        blocks: &'a BasicBlocks<'b, I>,
+        pred: HashMap<String, Vec<ContextRef<'b, BasicBlock<I>>>>,
+        succ: HashMap<String, Vec<ContextRef<'b, BasicBlock<I>>>>,
+    ) -> Result<Self, CompilerError> {
         // Validate entry / exit blocks are present and have no
         // predecessors / successors respectively
         if blocks.entry().is_none() {
@@ -51,11 +68,11 @@ impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
         assert!(blocks.get(entry.label()).is_ok());
         assert!(blocks.get(exit.label()).is_ok());
 
-        if pred.get(entry.label()).unwrap().len() > 0 {
+        if pred.get(entry.label()).is_some_and(|pred| !pred.is_empty()) {
             return Err(CompilerErrorType::ControlFlowEntryBlockHasPredecessors
                 .with_label(entry.label().clone()));
         }
-        if succ.get(exit.label()).unwrap().len() > 0 {
+        if succ.get(exit.label()).is_some_and(|succ| !succ.is_empty()) {
             return Err(CompilerErrorType::ControlFlowExitBlockHasSuccessors
                 .with_label(exit.label().clone()));
         }
@@ -70,6 +87,17 @@ impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
         Ok(cfg)
     }
 
+    /**
+     * Releases the blocks so they can be changed, keeping the edges for
+     * `ControlFlowEdges::update`.
+     */
+    pub fn into_edges(self) -> ControlFlowEdges<'b, I> {
+        ControlFlowEdges {
+            pred: self.pred,
+            succ: self.succ,
+        }
+    }
+
     pub fn blocks(&self) -> impl Iterator<Item = ContextRef<'b, BasicBlock<I>>> + '_ {
         self.blocks.blocks()
     }
@@ -79,7 +107,7 @@ impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
     }
 
     pub fn exit(&self) -> ContextRef<'b, BasicBlock<I>> {
-        self.entry
+        self.exit
     }
 
     pub fn predecessors(
@@ -103,9 +131,69 @@ impl<'a, 'b, I: 'static + Instruction> ControlFlowGraph<'a, 'b, I> {
     }
 }
 
+impl<'b, I: 'static + Instruction> ControlFlowEdges<'b, I> {
+    /**
+     * Rebuilds the control flow graph after the blocks with the given labels
+     * were inserted, replaced or removed, as returned by
+     * `BasicBlocks::take_changes`. Only the edges out of the changed blocks
+     * are recomputed, the result is the same as `ControlFlowGraph::new` up to
+     * the order of predecessors.
+     */
+    pub fn update<'a>(
+        mut self,
+        blocks: &'a BasicBlocks<'b, I>,
+        changes: &[String],
+    ) -> Result<ControlFlowGraph<'a, 'b, I>, CompilerError> {
+        for label in changes {
+            for succ in self.succ.remove(label).unwrap_or_default() {
+                if let Some(pred) = self.pred.get_mut(succ.label()) {
+                    pred.retain(|pred| pred.label() != label);
+                }
+            }
+        }
+        for label in changes {
+            let block = match blocks.get(label) {
+                Ok(block) => block,
+                Err(_) => {
+                    // Removed, its predecessors must have changed as well
+                    self.pred.remove(label);
+                    continue;
+                }
+            };
+            let succ = block
+                .successors()
+                .iter()
+                .map(|label| blocks.get(label))
+                .collect::<Result<Vec<_>, _>>()?;
+            for target in succ.iter() {
+                self.pred
+                    .entry(target.label().clone())
+                    .or_default()
+                    .push(block);
+            }
+            self.succ.insert(label.clone(), succ);
+        }
+
+        // Unchanged predecessors still refer to the old version of the block
+        for label in changes {
+            if let Ok(block) = blocks.get(label) {
+                for pred in self.pred.get(label).into_iter().flatten() {
+                    for succ in self.succ.get_mut(pred.label()).into_iter().flatten() {
+                        if succ.label() == label {
+                            *succ = block;
+                        }
+                    }
+                }
+            }
+        }
+        ControlFlowGraph::from_edges(blocks, self.pred, self.succ)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bril::EffectOps;
     use crate::v2::context::Context;
     use crate::v2::instruction::BrilInstruction;
 
@@ -115,6 +203,126 @@ mod tests {
         let bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
         let _cfg = ControlFlowGraph::new(&bbs);
     }
+
+    fn block(label: &str, instrs: Vec<BrilInstruction>) -> BasicBlock<BrilInstruction> {
+        BasicBlock::new(label.to_string(), instrs).unwrap()
+    }
+
+    fn jump(label: &str) -> BrilInstruction {
+        BrilInstruction::jump(label.to_string())
+    }
+
+    fn branch(t: &str, f: &str) -> BrilInstruction {
+        BrilInstruction::effect(
+            EffectOps::Branch,
+            vec!["c".to_string()],
+            Vec::new(),
+            vec![t.to_string(), f.to_string()],
+        )
+    }
+
+    #[test]
+    fn test_unreachable_block() {
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let entry = bbs
+            .insert_block(block("entry", vec![jump("exit")]))
+            .unwrap();
+        let dead = bbs.insert_block(block("dead", vec![jump("exit")])).unwrap();
+        let exit = bbs
+            .insert_block(block("exit", vec![BrilInstruction::ret()]))
+            .unwrap();
+        bbs.set_entry(entry).unwrap();
+        bbs.set_exit(exit).unwrap();
+
+        let cfg = ControlFlowGraph::new(&bbs).unwrap();
+        assert_eq!(cfg.entry(), entry);
+        assert_eq!(cfg.exit(), exit);
+        assert!(cfg.predecessors(dead).is_empty());
+        assert!(cfg.predecessors(entry).is_empty());
+        assert!(cfg.successors(exit).is_empty());
+        assert_eq!(cfg.predecessors(exit), &[entry, dead]);
+    }
+
+    /**
+     * Every block with its sorted predecessors and successors, checking that
+     * the graph refers to the current version of each block.
+     */
+    fn edges<'b>(
+        cfg: &ControlFlowGraph<'_, 'b, BrilInstruction>,
+        bbs: &BasicBlocks<'b, BrilInstruction>,
+    ) -> Vec<(String, Vec<String>, Vec<String>)> {
+        let labels = |blocks: &[ContextRef<'b, BasicBlock<BrilInstruction>>]| {
+            let mut labels: Vec<_> = blocks
+                .iter()
+                .map(|block| {
+                    let current = bbs.get(block.label()).unwrap();
+                    assert_eq!(block.successors(), current.successors());
+                    block.label().clone()
+                })
+                .collect();
+            labels.sort();
+            labels
+        };
+        let mut edges: Vec<_> = bbs
+            .blocks()
+            .map(|block| {
+                (
+                    block.label().clone(),
+                    labels(cfg.predecessors(block)),
+                    labels(cfg.successors(block)),
+                )
+            })
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    #[test]
+    fn test_update() {
+        // entry -> (left | join), left -> (left | join), join -> exit
+        let ctx = Context::new();
+        let mut bbs = BasicBlocks::<BrilInstruction>::new(&ctx, "_".to_string());
+        let entry = bbs
+            .insert_block(block("entry", vec![branch("left", "join")]))
+            .unwrap();
+        bbs.insert_block(block("left", vec![branch("left", "join")]))
+            .unwrap();
+        bbs.insert_block(block("join", vec![jump("exit")])).unwrap();
+        let exit = bbs
+            .insert_block(block("exit", vec![BrilInstruction::ret()]))
+            .unwrap();
+        bbs.set_entry(entry).unwrap();
+        bbs.set_exit(exit).unwrap();
+        bbs.take_changes();
+
+        let cfg = ControlFlowGraph::new(&bbs).unwrap();
+        assert_eq!(Graph::predecessors(&cfg, entry).count(), 0);
+        assert_eq!(Graph::successors(&cfg, exit).count(), 0);
+        let cfg_edges = cfg.into_edges();
+
+        let split = bbs.split_critical_edges().unwrap();
+        assert_eq!(split.len(), 4);
+        let changes = bbs.take_changes();
+        let cfg = cfg_edges.update(&bbs, &changes).unwrap();
+        assert_eq!(
+            edges(&cfg, &bbs),
+            edges(&ControlFlowGraph::new(&bbs).unwrap(), &bbs)
+        );
+        let cfg_edges = cfg.into_edges();
+
+        // Removing the block on the self loop leaves left jumping to join
+        let self_loop = bbs.get("left").unwrap().successors()[0].clone();
+        bbs.remove_block(&self_loop).unwrap();
+        let changes = bbs.take_changes();
+        let cfg = cfg_edges.update(&bbs, &changes).unwrap();
+        let expected = ControlFlowGraph::new(&bbs).unwrap();
+        assert_eq!(edges(&cfg, &bbs), edges(&expected, &bbs));
+        assert_eq!(cfg.blocks().count(), 7);
+        let left = bbs.get("left").unwrap();
+        assert_eq!(cfg.successors(left).len(), 1);
+        assert_eq!(cfg.predecessors(left).len(), 1);
+    }
 }
 
 impl<'a, 'b, I: 'static + Instruction> Graph for ControlFlowGraph<'a, 'b, I> {
@@ -133,10 +341,10 @@ impl<'a, 'b, I: 'static + Instruction> Graph for ControlFlowGraph<'a, 'b, I> {
     }
 
     fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
-        self.pred.get(node.label()).unwrap().iter().cloned()
+        ControlFlowGraph::predecessors(self, node).iter().cloned()
     }
 
     fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
-        self.succ.get(node.label()).unwrap().iter().cloned()
+        ControlFlowGraph::successors(self, node).iter().cloned()
     }
 }
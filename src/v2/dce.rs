@@ -0,0 +1,120 @@
+use crate::v2::basic_block::{BasicBlock, BasicBlocks};
+use crate::v2::error::CompilerError;
+use crate::v2::function::Function;
+use crate::v2::instruction::{BrilInstruction, Instruction};
+use std::collections::HashSet;
+
+/**
+ * The instructions of the block without the assignments that are assigned
+ * again later in the block before being used, or `None` if there are none.
+ */
+fn block_dce(block: &BasicBlock<BrilInstruction>) -> Option<Vec<BrilInstruction>> {
+    // Walking backwards, variables whose current value is never read
+    let mut dead = HashSet::new();
+    let mut instrs = Vec::with_capacity(block.instrs().len());
+    for instr in block.instrs().iter().rev() {
+        if let Some(dest) = instr.dest() {
+            if !dead.insert(dest) {
+                continue;
+            }
+        }
+        for arg in instr.args() {
+            dead.remove(arg);
+        }
+        instrs.push(instr.clone());
+    }
+    if instrs.len() == block.instrs().len() {
+        return None;
+    }
+    instrs.reverse();
+    Some(instrs)
+}
+
+/**
+ * Drops the assignments to variables that no instruction uses, returning
+ * whether anything was dropped.
+ */
+fn global_dce(blocks: &mut BasicBlocks<'_, BrilInstruction>) -> Result<bool, CompilerError> {
+    let mut used = HashSet::new();
+    for block in blocks.blocks() {
+        for instr in block.instrs() {
+            used.extend(instr.args().iter().cloned());
+        }
+    }
+    let mut changed = Vec::new();
+    for block in blocks.blocks() {
+        let instrs: Vec<_> = block
+            .instrs()
+            .iter()
+            .filter(|instr| instr.dest().is_none_or(|dest| used.contains(dest)))
+            .cloned()
+            .collect();
+        if instrs.len() < block.instrs().len() {
+            changed.push(BasicBlock::new(block.label().clone(), instrs)?);
+        }
+    }
+    let dropped = !changed.is_empty();
+    for block in changed {
+        blocks.replace_block(block)?;
+    }
+    Ok(dropped)
+}
+
+/**
+ * Trivial dead code elimination on the v2 IR, the same as
+ * `crate::dce::trivial_dce` does for a whole program.
+ */
+pub fn trivial_dce(function: &mut Function<BrilInstruction>) -> Result<(), CompilerError> {
+    function.update_blocks(|blocks| {
+        let labels: Vec<_> = blocks.blocks().map(|block| block.label().clone()).collect();
+        for label in labels {
+            let block = blocks.get(&label)?;
+            if let Some(instrs) = block_dce(&block) {
+                blocks.replace_block(BasicBlock::new(label, instrs)?)?;
+            }
+        }
+        while global_dce(blocks)? {}
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bril;
+
+    fn program(instrs: &str) -> bril::Function {
+        let program = format!(
+            r#"{{"functions": [{{"name": "main", "args": [{{"name": "a", "type": "int"}}], "instrs": {}}}]}}"#,
+            instrs
+        );
+        let program: bril::Program = serde_json::from_str(&program).unwrap();
+        program.functions[0].clone()
+    }
+
+    #[test]
+    fn test_trivial_dce() {
+        let original = program(
+            r#"[
+                {"dest": "b", "op": "const", "type": "int", "value": 1},
+                {"dest": "b", "op": "const", "type": "int", "value": 2},
+                {"dest": "c", "op": "add", "type": "int", "args": ["a", "b"]},
+                {"dest": "d", "op": "add", "type": "int", "args": ["c", "c"]},
+                {"dest": "e", "op": "id", "type": "int", "args": ["d"]},
+                {"label": "next"},
+                {"op": "print", "args": ["c"]}
+            ]"#,
+        );
+        let mut function = Function::<BrilInstruction>::from_bril(&original).unwrap();
+        trivial_dce(&mut function).unwrap();
+        let expected = program(
+            r#"[
+                {"dest": "b", "op": "const", "type": "int", "value": 2},
+                {"dest": "c", "op": "add", "type": "int", "args": ["a", "b"]},
+                {"label": "next"},
+                {"op": "print", "args": ["c"]}
+            ]"#,
+        );
+        assert_eq!(function.to_bril().instrs, expected.instrs);
+    }
+}
@@ -1,39 +1,318 @@
 use super::graph::Graph;
 use std::{
     collections::{HashMap, HashSet},
+    marker::PhantomData,
     vec,
 };
 
+/**
+ * Immediate dominators of the nodes reachable from the entry, computed with
+ * the "simple, fast" algorithm of Cooper, Harvey and Kennedy over reverse
+ * postorder numbers. The entry has no immediate dominator.
+ */
+pub fn immediate_dominators<G: Graph>(graph: &G) -> HashMap<G::Node, G::Node> {
+    let order: Vec<_> = graph.post_order_iter().rev().collect();
+    let numbers: HashMap<_, _> = order
+        .iter()
+        .enumerate()
+        .map(|(number, node)| (*node, number))
+        .collect();
+    let predecessors: Vec<Vec<usize>> = order
+        .iter()
+        .map(|node| {
+            graph
+                .predecessors(*node)
+                .filter_map(|pred| numbers.get(&pred).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut idom: Vec<Option<usize>> = vec![None; order.len()];
+    if let Some(entry) = idom.first_mut() {
+        *entry = Some(0);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in 1..order.len() {
+            // The parent in the DFS tree comes earlier, so some predecessor is done
+            let mut done = predecessors[node]
+                .iter()
+                .copied()
+                .filter(|pred| idom[*pred].is_some());
+            let first = done.next().unwrap();
+            let new_idom = done.fold(first, |a, b| intersect(&idom, a, b));
+            if idom[node] != Some(new_idom) {
+                idom[node] = Some(new_idom);
+                changed = true;
+            }
+        }
+    }
+
+    idom.into_iter()
+        .enumerate()
+        .skip(1)
+        .map(|(node, dominator)| (order[node], order[dominator.unwrap()]))
+        .collect()
+}
+
+/** Walks both nodes up the tree to their closest common dominator */
+fn intersect(idom: &[Option<usize>], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while a > b {
+            a = idom[a].unwrap();
+        }
+        while b > a {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+/**
+ * A change to the edges of a graph, see `DominanceTree::update`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeUpdate<N> {
+    Insert(N, N),
+    Delete(N, N),
+}
+
+/**
+ * The part of a graph reachable from `root` through nodes that pass `allowed`.
+ */
+struct Region<'g, H: Graph, F: Fn(H::Node) -> bool> {
+    graph: &'g H,
+    root: H::Node,
+    allowed: F,
+}
+
+impl<'g, H: Graph, F: Fn(H::Node) -> bool> Graph for Region<'g, H, F> {
+    type Node = H::Node;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        Some(self.root)
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        None
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        self.graph.nodes().filter(move |node| (self.allowed)(*node))
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.graph
+            .predecessors(node)
+            .filter(move |pred| (self.allowed)(*pred))
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.graph
+            .successors(node)
+            .filter(move |succ| (self.allowed)(*succ))
+    }
+}
+
 pub struct DominanceTree<'a, G: Graph> {
     reachable_nodes: HashSet<G::Node>,
     immediately_dominates: HashMap<G::Node, Vec<G::Node>>,
     immediate_dominator: HashMap<G::Node, G::Node>,
-    graph: &'a G,
+    /** Pre and post order numbers of each reachable node in the tree */
+    numbering: HashMap<G::Node, (usize, usize)>,
+    entry: Option<G::Node>,
+    /** Successors of each reachable node, for the dominance frontiers */
+    successors: HashMap<G::Node, Vec<G::Node>>,
+    graph: PhantomData<&'a G>,
 }
 
 impl<'a, G: Graph> DominanceTree<'a, G> {
     pub fn new(graph: &'a G) -> Self {
-        let reachable_nodes = graph.pre_order_iter().collect();
-        let immediately_dominates = Self::build_immediately_dominates(graph);
-        let immediate_dominator = Self::build_immediate_dominator(&immediately_dominates);
-        let immediately_dominates = immediately_dominates
-            .into_iter()
-            .map(|(node, dominated)| (node, dominated.into_iter().collect()))
+        Self::build(graph)
+    }
+
+    /**
+     * Builds the tree of any graph over the same nodes, such as the inverse
+     * of `G` for post-dominators.
+     */
+    pub(crate) fn build<H: Graph<Node = G::Node>>(graph: &H) -> Self {
+        let reachable_nodes: HashSet<_> = graph.pre_order_iter().collect();
+        let immediate_dominator = immediate_dominators(graph);
+        let mut immediately_dominates: HashMap<G::Node, Vec<G::Node>> = HashMap::new();
+        for node in graph.post_order_iter().rev() {
+            if let Some(dominator) = immediate_dominator.get(&node) {
+                immediately_dominates
+                    .entry(*dominator)
+                    .or_default()
+                    .push(node);
+            }
+        }
+        let entry = graph.entry_node();
+        let numbering = Self::build_numbering(entry, &immediately_dominates);
+        let successors = reachable_nodes
+            .iter()
+            .map(|node| (*node, graph.successors(*node).collect()))
             .collect();
         DominanceTree {
             reachable_nodes,
             immediately_dominates,
             immediate_dominator,
+            numbering,
+            entry,
+            successors,
+            graph: PhantomData,
+        }
+    }
+
+    /**
+     * Brings the tree up to date with `graph`, which is the graph the tree
+     * was built for with the given edges inserted or deleted. Inserted edges
+     * may lead to new nodes. Only the subtree rooted at the nearest common
+     * dominator of the changed edges is recomputed, unless nodes in it
+     * become reachable or unreachable while having edges leaving it, in which
+     * case the whole tree is rebuilt. Debug builds check the result against
+     * a full rebuild.
+     */
+    pub fn update(&mut self, graph: &G, updates: &[EdgeUpdate<G::Node>]) {
+        self.update_with(graph, updates)
+    }
+
+    pub(crate) fn update_with<H: Graph<Node = G::Node>>(
+        &mut self,
+        graph: &H,
+        updates: &[EdgeUpdate<G::Node>],
+    ) {
+        if !self.update_region(graph, updates) {
+            *self = Self::build(graph);
+        }
+        if cfg!(debug_assertions) {
+            let rebuilt = Self::build(graph);
+            assert_eq!(self.reachable_nodes, rebuilt.reachable_nodes);
+            assert_eq!(self.immediate_dominator, rebuilt.immediate_dominator);
+        }
+    }
+
+    /**
+     * The deepest node dominating both nodes, which must be reachable.
+     */
+    fn nearest_common_dominator(&self, a: G::Node, b: G::Node) -> G::Node {
+        let mut dominator = a;
+        while !self.dominates(dominator, b) {
+            dominator = self.immediate_dominator[&dominator];
+        }
+        dominator
+    }
+
+    /**
+     * Recomputes the subtree affected by the updates, returning false if
+     * the change can reach outside of it and the whole tree needs rebuilding.
+     */
+    fn update_region<H: Graph<Node = G::Node>>(
+        &mut self,
+        graph: &H,
+        updates: &[EdgeUpdate<G::Node>],
+    ) -> bool {
+        if graph.entry_node() != self.entry {
+            return false;
+        }
+        // Edges out of unreachable nodes don't matter, unless the node
+        // becomes reachable through another update, which the search finds
+        let mut root: Option<G::Node> = None;
+        for update in updates {
+            let (from, to) = match update {
+                EdgeUpdate::Insert(from, to) | EdgeUpdate::Delete(from, to) => (*from, *to),
+            };
+            if !self.reachable_nodes.contains(&from) {
+                continue;
+            }
+            let mut affected = if self.reachable_nodes.contains(&to) {
+                self.nearest_common_dominator(from, to)
+            } else {
+                from
+            };
+            if let Some(root) = root {
+                affected = self.nearest_common_dominator(root, affected);
+            }
+            root = Some(affected);
+        }
+        let root = match root {
+            Some(root) if Some(root) != self.entry => root,
+            Some(_) => return false,
+            None => return true,
+        };
+
+        // Everything entering the old subtree goes through the root, so only
+        // the subtree and nodes that weren't reachable before can change
+        let old_nodes: HashSet<_> = self.dominated_nodes(root).collect();
+        let in_region =
+            |node: G::Node| old_nodes.contains(&node) || !self.reachable_nodes.contains(&node);
+        let region = Region {
             graph,
+            root,
+            allowed: in_region,
+        };
+        let new_nodes: Vec<_> = region.post_order_iter().rev().collect();
+        let new_set: HashSet<_> = new_nodes.iter().copied().collect();
+        let leaves = |node: &G::Node| graph.successors(*node).any(|succ| !in_region(succ));
+        if new_nodes
+            .iter()
+            .filter(|node| !old_nodes.contains(node))
+            .chain(old_nodes.iter().filter(|node| !new_set.contains(node)))
+            .any(leaves)
+        {
+            return false;
+        }
+        let region_idom = immediate_dominators(&region);
+
+        for node in old_nodes.iter() {
+            self.immediately_dominates.remove(node);
+            self.successors.remove(node);
+            if *node != root {
+                self.immediate_dominator.remove(node);
+                self.reachable_nodes.remove(node);
+            }
+        }
+        for node in new_nodes.iter() {
+            self.reachable_nodes.insert(*node);
+            self.successors
+                .insert(*node, graph.successors(*node).collect());
+            if let Some(dominator) = region_idom.get(node) {
+                self.immediate_dominator.insert(*node, *dominator);
+                self.immediately_dominates
+                    .entry(*dominator)
+                    .or_default()
+                    .push(*node);
+            }
+        }
+        self.numbering = Self::build_numbering(self.entry, &self.immediately_dominates);
+        true
+    }
+
+    /**
+     * Whether every path from the entry to `dominated` goes through
+     * `dominator`. Unreachable nodes neither dominate nor are dominated.
+     */
+    pub fn dominates(&self, dominator: G::Node, dominated: G::Node) -> bool {
+        match (
+            self.numbering.get(&dominator),
+            self.numbering.get(&dominated),
+        ) {
+            (Some((pre_a, post_a)), Some((pre_b, post_b))) => pre_a <= pre_b && post_b <= post_a,
+            _ => false,
         }
     }
 
+    pub fn strictly_dominates(&self, dominator: G::Node, dominated: G::Node) -> bool {
+        dominator != dominated && self.dominates(dominator, dominated)
+    }
+
     pub fn immediate_dominator(&self, node: G::Node) -> Option<G::Node> {
         let dominator = self.immediate_dominator.get(&node).map(|x| *x);
         assert!(
             dominator.is_some()
                 || !self.reachable_nodes.contains(&node)
-                || Some(node) == self.graph.entry_node()
+                || Some(node) == self.entry
         );
         dominator
     }
@@ -66,12 +345,15 @@ impl<'a, G: Graph> DominanceTree<'a, G> {
     }
 
     pub fn dominance_frontier(&self, node: G::Node) -> impl Iterator<Item = G::Node> + '_ {
-        let strictly_dominated = self.strictly_dominated_nodes(node).collect::<HashSet<_>>();
-        let dominance_frontier = self
+        // Dedup in the order found rather than through a set, which would
+        // iterate in a different order on every run
+        let mut seen = HashSet::new();
+        let dominance_frontier: Vec<_> = self
             .dominated_nodes(node)
-            .flat_map(|dom| self.graph.successors(dom))
-            .filter(|succ| !strictly_dominated.contains(succ))
-            .collect::<HashSet<_>>();
+            .flat_map(|dom| self.successors[&dom].iter().copied())
+            .filter(|succ| !self.strictly_dominates(node, *succ))
+            .filter(|succ| seen.insert(*succ))
+            .collect();
         dominance_frontier.into_iter()
     }
 }
@@ -94,109 +376,28 @@ impl<'a, 'b, G: Graph> Iterator for DominatedIterator<'a, 'b, G> {
 }
 
 impl<'a, G: Graph> DominanceTree<'a, G> {
-    fn build_immediately_dominates(graph: &'a G) -> HashMap<G::Node, HashSet<G::Node>> {
-        let dominators = Self::build_dominators(graph);
-        let strictly_dominates = Self::build_strictly_dominates(&dominators);
-        let mut imm_dom = HashMap::new();
-
-        for (node, dominated) in &strictly_dominates {
-            for dom in dominated {
-                assert!(dominators[dom].contains(node));
-                let intersection = dominators[dom].intersection(dominated).count();
-                if intersection == 1 {
-                    imm_dom
-                        .entry(*node)
-                        .or_insert_with(HashSet::new)
-                        .insert(*dom);
-                }
-            }
-        }
-
-        imm_dom
-    }
-
-    fn build_immediate_dominator(
-        immediately_dominates: &HashMap<G::Node, HashSet<G::Node>>,
-    ) -> HashMap<G::Node, G::Node> {
-        let mut immediate_dominator = HashMap::new();
-
-        for (dominator, dominated) in immediately_dominates {
-            for node in dominated {
-                assert!(!immediate_dominator.contains_key(node));
-                immediate_dominator.insert(*node, *dominator);
-            }
-        }
-
-        immediate_dominator
-    }
-
-    fn build_strictly_dominates(
-        dominators: &HashMap<G::Node, HashSet<G::Node>>,
-    ) -> HashMap<G::Node, HashSet<G::Node>> {
-        let mut dominates: HashMap<_, _> = dominators
-            .keys()
-            .cloned()
-            .map(|node| (node, HashSet::new()))
-            .collect();
-
-        for (node, dominators) in dominators {
-            for dominator in dominators {
-                dominates.get_mut(dominator).unwrap().insert(*node);
-            }
-        }
-
-        for (node, dominated) in &mut dominates {
-            dominated.remove(node);
-        }
-
-        dominates
-    }
-
-    fn build_dominators(graph: &'a G) -> HashMap<G::Node, HashSet<G::Node>> {
-        let mut dom = HashMap::new();
-        if graph.entry_node().is_none() {
-            // No entry node => no node dominates any other
-            return dom;
-        }
-
-        // Initialize the entry node to be dominated by only itself
-        let entry_node = graph.entry_node().unwrap();
-        dom.insert(entry_node, HashSet::from([entry_node]));
-
-        let iter = graph.post_order_iter().rev();
-        assert!(iter.clone().next() == graph.entry_node());
-
-        // Initialize every other node reachable from the entry node to be
-        // dominated by every node reachable from the entry node.
-        for node in iter.clone().skip(1) {
-            dom.insert(node, iter.clone().collect());
-        }
-        let reachable_nodes = iter.clone().collect::<HashSet<_>>();
-
-        loop {
-            let mut changed = false;
-            for node in iter.clone().skip(1) {
-                assert!(Some(node) != graph.entry_node());
-                let pred_dom = graph
-                    .predecessors(node)
-                    .filter(|node| reachable_nodes.contains(node))
-                    .map(|pred| dom.get(&pred).unwrap())
-                    .fold(None, |acc, x| match acc {
-                        Some(acc) => Some(&acc & x),
-                        None => Some(x.clone()),
-                    });
-                let mut pred_dom = pred_dom.unwrap_or_default();
-                pred_dom.insert(node);
-                dom.entry(node).and_modify(|x| {
-                    changed |= x != &pred_dom;
-                    *x = pred_dom;
-                });
+    fn build_numbering(
+        entry: Option<G::Node>,
+        immediately_dominates: &HashMap<G::Node, Vec<G::Node>>,
+    ) -> HashMap<G::Node, (usize, usize)> {
+        let mut pre = HashMap::new();
+        let mut numbering = HashMap::new();
+        let mut next_post = 0;
+        // Each node is pushed twice, the second time to number it on the way out
+        let mut stack: Vec<(G::Node, bool)> = entry.map(|entry| (entry, false)).into_iter().collect();
+        while let Some((node, visited)) = stack.pop() {
+            if visited {
+                numbering.insert(node, (pre[&node], next_post));
+                next_post += 1;
+                continue;
             }
-            if !changed {
-                break;
+            pre.insert(node, pre.len());
+            stack.push((node, true));
+            if let Some(dominated) = immediately_dominates.get(&node) {
+                stack.extend(dominated.iter().map(|node| (*node, false)));
             }
         }
-        dom
+        numbering
     }
 }
 
@@ -204,7 +405,7 @@ impl<'a, G:Graph> Graph for DominanceTree<'a, G> {
     type Node = G::Node;
 
     fn entry_node(&self) -> Option<Self::Node> {
-        self.graph.entry_node()
+        self.entry
     }
 
     fn exit_node(&self) -> Option<Self::Node> {
@@ -213,7 +414,10 @@ impl<'a, G:Graph> Graph for DominanceTree<'a, G> {
     }
 
     fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
-        self.reachable_nodes.iter().cloned()
+        // In pre-order, so the order doesn't depend on how the nodes hash
+        let mut nodes: Vec<_> = self.reachable_nodes.iter().cloned().collect();
+        nodes.sort_by_key(|node| self.numbering[node].0);
+        nodes.into_iter()
     }
 
     fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
@@ -325,6 +529,18 @@ mod tests {
                 assert_eq!(dom.immediate_dominator(*dominated), Some(dominator));
             }
 
+            // Test that the numbering agrees with the dominated nodes
+            for node in &all_nodes {
+                assert_eq!(
+                    dom.dominates(dominator, *node),
+                    dominated_nodes.contains(node)
+                );
+                assert_eq!(
+                    dom.strictly_dominates(dominator, *node),
+                    strictly_dominated_nodes.contains(node)
+                );
+            }
+
             // Test that the dominance frontier is correct
             let dominance_frontier = dom.dominance_frontier(dominator).collect::<HashSet<_>>();
             // No node in the dominance frontier is strictly dominated
@@ -364,9 +580,54 @@ mod tests {
         assert_eq!(dom.immediate_dominator(5), Some(2));
         assert_eq!(dom.immediate_dominator(6), Some(2));
 
+        // The nodes come in pre-order, each after its immediate dominator
+        let nodes: Vec<_> = dom.nodes().collect();
+        assert_eq!(nodes.len(), 6);
+        assert_eq!(nodes[..2], [1, 2]);
+        for (pos, node) in nodes.iter().enumerate() {
+            if let Some(idom) = dom.immediate_dominator(*node) {
+                assert!(nodes[..pos].contains(&idom));
+            }
+        }
+        assert!(dom.nodes().eq(nodes));
+
         test_dominance_tree(&graph, &dom);
     }
 
+    #[test]
+    fn test_large_graph() {
+        // A chain of diamonds, each top immediately dominating the rest of its diamond
+        let diamonds = 2000;
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..3 * diamonds + 1);
+        for diamond in 0..diamonds {
+            let top = 3 * diamond;
+            graph.extend_edges(
+                vec![
+                    (top, top + 1),
+                    (top, top + 2),
+                    (top + 1, top + 3),
+                    (top + 2, top + 3),
+                ]
+                .into_iter(),
+            );
+        }
+        // And a back edge around the whole chain
+        graph.add_edge(3 * diamonds, 0);
+        let dom = DominanceTree::new(&graph);
+
+        assert_eq!(dom.immediate_dominator(0), None);
+        for diamond in 0..diamonds {
+            let top = 3 * diamond;
+            for node in top + 1..top + 4 {
+                assert_eq!(dom.immediate_dominator(node), Some(top));
+            }
+        }
+        assert!(dom.dominates(0, 3 * diamonds));
+        assert!(!dom.dominates(1, 3 * diamonds));
+    }
+
     #[test]
     fn test_random_graphs() {
         let mut rng = StdRng::seed_from_u64(0);
@@ -386,4 +647,132 @@ mod tests {
             test_dominance_tree(&graph, &dom);
         }
     }
+
+    /**
+     * Applies the updates to a copy of the graph, then checks the updated tree
+     * against the graph. The update itself checks against a full rebuild.
+     */
+    fn check_update(graph: &SimpleGraph, updates: &[EdgeUpdate<i32>]) -> SimpleGraph {
+        let mut updated = graph.clone();
+        for update in updates {
+            match update {
+                EdgeUpdate::Insert(from, to) => {
+                    if !updated.nodes().any(|node| node == *to) {
+                        updated.add_node(*to);
+                    }
+                    updated.add_edge(*from, *to)
+                }
+                EdgeUpdate::Delete(from, to) => updated.remove_edge(*from, *to),
+            }
+        }
+        let mut dom: DominanceTree<SimpleGraph> = DominanceTree::build(graph);
+        dom.update(&updated, updates);
+        test_dominance_tree(&updated, &dom);
+        updated
+    }
+
+    #[test]
+    fn test_update() {
+        // 0 -> 1 -> 2 -> 3 -> 4, 1 -> 5 -> 6 -> 4
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..7);
+        graph
+            .extend_edges(vec![(0, 1), (1, 2), (2, 3), (3, 4), (1, 5), (5, 6), (6, 4)].into_iter());
+        let mut dom: DominanceTree<SimpleGraph> = DominanceTree::build(&graph);
+        assert_eq!(dom.immediate_dominator(3), Some(2));
+
+        // A bypass around 2 moves 3 up to 1, but leaves 0 alone
+        graph.add_edge(5, 3);
+        dom.update(&graph, &[EdgeUpdate::Insert(5, 3)]);
+        assert_eq!(dom.immediate_dominator(3), Some(1));
+        assert_eq!(dom.immediate_dominator(4), Some(1));
+        assert_eq!(dom.immediate_dominator(1), Some(0));
+
+        // Splitting the edge 5 -> 3 with a new node 7
+        graph.add_node(7);
+        graph.remove_edge(5, 3);
+        graph.extend_edges(vec![(5, 7), (7, 3)].into_iter());
+        dom.update(
+            &graph,
+            &[
+                EdgeUpdate::Delete(5, 3),
+                EdgeUpdate::Insert(5, 7),
+                EdgeUpdate::Insert(7, 3),
+            ],
+        );
+        assert_eq!(dom.immediate_dominator(7), Some(5));
+        assert_eq!(dom.immediate_dominator(3), Some(1));
+        assert!(dom.dominates(5, 7));
+
+        // Taking the bypass away again
+        graph.remove_edge(5, 7);
+        dom.update(&graph, &[EdgeUpdate::Delete(5, 7)]);
+        assert_eq!(dom.immediate_dominator(3), Some(2));
+        assert_eq!(dom.immediate_dominator(7), None);
+        assert!(!dom.dominates(7, 7));
+        test_dominance_tree(&graph, &dom);
+    }
+
+    #[test]
+    fn test_update_unreachable() {
+        // 0 -> (1 | 2), 1 -> 3, 2 -> 3. Deleting 0 -> 1 makes 1 unreachable,
+        // which changes the idom of 3 even though it is outside 1's subtree
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (0, 2), (1, 3), (2, 3)].into_iter());
+        let mut dom: DominanceTree<SimpleGraph> = DominanceTree::build(&graph);
+        assert_eq!(dom.immediate_dominator(3), Some(0));
+        graph.remove_edge(0, 1);
+        dom.update(&graph, &[EdgeUpdate::Delete(0, 1)]);
+        assert_eq!(dom.immediate_dominator(3), Some(2));
+        assert_eq!(dom.immediate_dominator(1), None);
+        assert!(!dom.dominates(0, 1));
+
+        // Edges out of unreachable nodes change nothing
+        graph.remove_edge(1, 3);
+        dom.update(&graph, &[EdgeUpdate::Delete(1, 3)]);
+        assert_eq!(dom.immediate_dominator(3), Some(2));
+        test_dominance_tree(&graph, &dom);
+    }
+
+    #[test]
+    fn test_random_updates() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let mut graph = SimpleGraph::new();
+            graph.set_entry(0);
+            graph.extend_nodes(0..10);
+            let num_edges = Uniform::new(0, 30).sample(&mut rng);
+            for _ in 0..num_edges {
+                let src = Uniform::new(0, 10).sample(&mut rng);
+                let dst = Uniform::new(1, 10).sample(&mut rng);
+                graph.add_edge(src, dst);
+            }
+            for _ in 0..5 {
+                let num_updates = Uniform::new(1, 4).sample(&mut rng);
+                let mut updates = Vec::new();
+                for _ in 0..num_updates {
+                    let src = Uniform::new(0, 12).sample(&mut rng);
+                    let dst = Uniform::new(1, 12).sample(&mut rng);
+                    let is_edge = graph.successors(src).any(|succ| succ == dst);
+                    if is_edge || updates.contains(&EdgeUpdate::Insert(src, dst)) {
+                        continue;
+                    }
+                    updates.push(if rng.gen_bool(0.5) {
+                        EdgeUpdate::Insert(src, dst)
+                    } else {
+                        // Delete an existing edge out of src instead
+                        match graph.successors(src).next() {
+                            Some(succ) => EdgeUpdate::Delete(src, succ),
+                            None => EdgeUpdate::Insert(src, dst),
+                        }
+                    });
+                }
+                updates.dedup();
+                graph = check_update(&graph, &updates);
+            }
+        }
+    }
 }
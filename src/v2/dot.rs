@@ -0,0 +1,267 @@
+use super::dominance_tree::DominanceTree;
+use super::graph::Graph;
+use super::loops::{back_edges, LoopForest};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+type Labels<'a, N> = Box<dyn Fn(N) -> String + 'a>;
+type Bodies<'a, N> = Box<dyn Fn(N) -> Vec<String> + 'a>;
+
+/**
+ * Renders a graph in Graphviz's DOT language. Nodes are labeled with their
+ * debug representation unless labels are given, and can list a body of
+ * instructions under the label. Dominator tree edges, back edges and loop
+ * clusters all need the dominance tree, so they are only computed when asked
+ * for.
+ */
+pub struct Dot<'a, G: Graph> {
+    graph: &'a G,
+    name: String,
+    labels: Option<Labels<'a, G::Node>>,
+    bodies: Option<Bodies<'a, G::Node>>,
+    dominators: bool,
+    back_edges: bool,
+    loops: bool,
+}
+
+impl<'a, G: Graph> Dot<'a, G> {
+    pub fn new(graph: &'a G) -> Self {
+        Dot {
+            graph,
+            name: "G".to_string(),
+            labels: None,
+            bodies: None,
+            dominators: false,
+            back_edges: false,
+            loops: false,
+        }
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_labels(mut self, labels: impl Fn(G::Node) -> String + 'a) -> Self {
+        self.labels = Some(Box::new(labels));
+        self
+    }
+
+    /**
+     * Lists the lines returned for each node under its label.
+     */
+    pub fn with_bodies(mut self, bodies: impl Fn(G::Node) -> Vec<String> + 'a) -> Self {
+        self.bodies = Some(Box::new(bodies));
+        self
+    }
+
+    /**
+     * Adds the dominator tree as dashed edges that don't affect the layout.
+     */
+    pub fn with_dominators(mut self) -> Self {
+        self.dominators = true;
+        self
+    }
+
+    pub fn with_back_edges(mut self) -> Self {
+        self.back_edges = true;
+        self
+    }
+
+    /**
+     * Draws each natural loop as a cluster around its nodes, nested the same
+     * way as the loops.
+     */
+    pub fn with_loops(mut self) -> Self {
+        self.loops = true;
+        self
+    }
+
+    fn label(&self, node: G::Node) -> String {
+        match &self.labels {
+            Some(labels) => labels(node),
+            None => format!("{:?}", node),
+        }
+    }
+
+    fn write_node(
+        &self,
+        f: &mut Formatter<'_>,
+        indent: &str,
+        id: usize,
+        node: G::Node,
+    ) -> fmt::Result {
+        let mut label = escape(&self.label(node));
+        if let Some(bodies) = &self.bodies {
+            label.push_str("\\l");
+            for line in bodies(node) {
+                label.push_str("  ");
+                label.push_str(&escape(&line));
+                label.push_str("\\l");
+            }
+        }
+        write!(f, "{}n{} [label=\"{}\"", indent, id, label)?;
+        if Some(node) == self.graph.entry_node() || Some(node) == self.graph.exit_node() {
+            write!(f, ", penwidth=2")?;
+        }
+        writeln!(f, "];")
+    }
+
+    fn write_loop(
+        &self,
+        f: &mut Formatter<'_>,
+        forest: &LoopForest<G::Node>,
+        ids: &HashMap<G::Node, usize>,
+        nodes: &[G::Node],
+        idx: usize,
+    ) -> fmt::Result {
+        let indent = "  ".repeat(forest.depth(idx));
+        let header = forest.get(idx).header();
+        writeln!(f, "{}subgraph cluster_loop{} {{", indent, idx)?;
+        writeln!(
+            f,
+            "{}  label=\"loop {}\";",
+            indent,
+            escape(&self.label(header))
+        )?;
+        writeln!(f, "{}  style=dashed;", indent)?;
+        for &node in nodes {
+            if forest.innermost_loop(node) == Some(idx) {
+                self.write_node(f, &format!("{}  ", indent), ids[&node], node)?;
+            }
+        }
+        for &child in forest.children(idx) {
+            self.write_loop(f, forest, ids, nodes, child)?;
+        }
+        writeln!(f, "{}}}", indent)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<'a, G: Graph> Display for Dot<'a, G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let nodes: Vec<_> = self.graph.nodes().collect();
+        let ids: HashMap<_, _> = nodes.iter().enumerate().map(|(id, n)| (*n, id)).collect();
+        let dom = if self.dominators || self.back_edges || self.loops {
+            Some(DominanceTree::new(self.graph))
+        } else {
+            None
+        };
+
+        writeln!(f, "digraph \"{}\" {{", escape(&self.name))?;
+        writeln!(f, "  node [shape=box, fontname=\"monospace\"];")?;
+        match (&dom, self.loops) {
+            (Some(dom), true) => {
+                let forest = LoopForest::new(self.graph, dom);
+                for &node in &nodes {
+                    if forest.innermost_loop(node).is_none() {
+                        self.write_node(f, "  ", ids[&node], node)?;
+                    }
+                }
+                for idx in forest.roots() {
+                    self.write_loop(f, &forest, &ids, &nodes, idx)?;
+                }
+            }
+            _ => {
+                for &node in &nodes {
+                    self.write_node(f, "  ", ids[&node], node)?;
+                }
+            }
+        }
+
+        let back: HashSet<_> = match &dom {
+            Some(dom) if self.back_edges => back_edges(self.graph, dom).into_iter().collect(),
+            _ => HashSet::new(),
+        };
+        for &node in &nodes {
+            for succ in self.graph.successors(node) {
+                write!(f, "  n{} -> n{}", ids[&node], ids[&succ])?;
+                if back.contains(&(node, succ)) {
+                    write!(f, " [color=red, penwidth=2]")?;
+                }
+                writeln!(f, ";")?;
+            }
+        }
+        if let (Some(dom), true) = (&dom, self.dominators) {
+            for &node in &nodes {
+                if let Some(idom) = dom.immediate_dominator(node) {
+                    writeln!(
+                        f,
+                        "  n{} -> n{} [style=dashed, color=blue, constraint=false];",
+                        ids[&idom], ids[&node]
+                    )?;
+                }
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::graph::tests::SimpleGraph;
+
+    fn graph() -> SimpleGraph {
+        // 0 -> 1 -> 2 -> (1 | 3)
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (1, 2), (2, 1), (2, 3)].into_iter());
+        graph
+    }
+
+    #[test]
+    fn test_basic() {
+        let graph = graph();
+        let dot = Dot::new(&graph).with_name("test".to_string()).to_string();
+        let expected = "digraph \"test\" {
+  node [shape=box, fontname=\"monospace\"];
+  n0 [label=\"0\", penwidth=2];
+  n1 [label=\"1\"];
+  n2 [label=\"2\"];
+  n3 [label=\"3\"];
+  n0 -> n1;
+  n1 -> n2;
+  n2 -> n1;
+  n2 -> n3;
+}
+";
+        assert_eq!(dot, expected);
+    }
+
+    #[test]
+    fn test_everything() {
+        let graph = graph();
+        let dot = Dot::new(&graph)
+            .with_labels(|node| format!(".b{}", node))
+            .with_bodies(|node| vec![format!("print \"{}\";", node)])
+            .with_dominators()
+            .with_back_edges()
+            .with_loops()
+            .to_string();
+        let expected = "digraph \"G\" {
+  node [shape=box, fontname=\"monospace\"];
+  n0 [label=\".b0\\l  print \\\"0\\\";\\l\", penwidth=2];
+  n3 [label=\".b3\\l  print \\\"3\\\";\\l\"];
+  subgraph cluster_loop0 {
+    label=\"loop .b1\";
+    style=dashed;
+    n1 [label=\".b1\\l  print \\\"1\\\";\\l\"];
+    n2 [label=\".b2\\l  print \\\"2\\\";\\l\"];
+  }
+  n0 -> n1;
+  n1 -> n2;
+  n2 -> n1 [color=red, penwidth=2];
+  n2 -> n3;
+  n0 -> n1 [style=dashed, color=blue, constraint=false];
+  n1 -> n2 [style=dashed, color=blue, constraint=false];
+  n2 -> n3 [style=dashed, color=blue, constraint=false];
+}
+";
+        assert_eq!(dot, expected);
+    }
+}
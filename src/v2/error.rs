@@ -11,6 +11,10 @@ pub enum CompilerErrorType {
     ControlFlowNoExitBlock,
     ControlFlowEntryBlockHasPredecessors,
     ControlFlowExitBlockHasSuccessors,
+    ControlFlowMissingEdge,
+    ControlFlowRemoveEntryOrExitBlock,
+    ControlFlowRemoveOnlySuccessor,
+    ControlFlowPreheaderWithPhis,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -0,0 +1,165 @@
+use crate::bril;
+use crate::v2::basic_block::BasicBlocks;
+use crate::v2::context::Context;
+use crate::v2::control_flow_graph::ControlFlowGraph;
+use crate::v2::error::CompilerError;
+use crate::v2::instruction::{Code, Instruction};
+
+/**
+ * A function in the v2 IR: its signature and its basic blocks, together with
+ * the context the blocks live in.
+ */
+pub struct Function<I: 'static + Instruction> {
+    name: String,
+    args: Vec<bril::Argument>,
+    return_type: Option<bril::Type>,
+    // Declared before `_ctx` so that the blocks are dropped first
+    blocks: BasicBlocks<'static, I>,
+    // Boxed so that the blocks' references stay valid when the function moves
+    _ctx: Box<Context>,
+}
+
+impl<I: 'static + Instruction> Function<I> {
+    pub fn new(
+        name: String,
+        args: Vec<bril::Argument>,
+        return_type: Option<bril::Type>,
+        code: &[Code<I>],
+    ) -> Result<Self, CompilerError> {
+        let ctx = Box::new(Context::new());
+        // The blocks never hand out references that outlive a borrow of the
+        // function, see `blocks` and `update_blocks`
+        let ctx_ref: &'static Context = unsafe { &*(&*ctx as *const Context) };
+        let blocks = BasicBlocks::from_code(ctx_ref, code)?;
+        Ok(Function {
+            name,
+            args,
+            return_type,
+            blocks,
+            _ctx: ctx,
+        })
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn args(&self) -> &[bril::Argument] {
+        &self.args
+    }
+
+    pub fn return_type(&self) -> Option<&bril::Type> {
+        self.return_type.as_ref()
+    }
+
+    pub fn blocks(&self) -> &BasicBlocks<'_, I> {
+        &self.blocks
+    }
+
+    /**
+     * Gives `f` mutable access to the blocks. Nothing borrowed from them can
+     * escape `f`, so the blocks can't be left holding references into
+     * another context or outlive this one.
+     */
+    pub fn update_blocks<R, F>(&mut self, f: F) -> R
+    where
+        F: for<'c> FnOnce(&mut BasicBlocks<'c, I>) -> R,
+    {
+        f(&mut self.blocks)
+    }
+
+    pub fn cfg(&self) -> Result<ControlFlowGraph<'_, '_, I>, CompilerError> {
+        ControlFlowGraph::new(self.blocks())
+    }
+
+    pub fn to_code(&self) -> Vec<Code<I>> {
+        self.blocks.to_code()
+    }
+}
+
+impl<I: 'static + Instruction + From<bril::Instruction>> Function<I> {
+    pub fn from_bril(function: &bril::Function) -> Result<Self, CompilerError> {
+        let code: Vec<Code<I>> = function.instrs.iter().cloned().map(Code::from).collect();
+        Function::new(
+            function.name.clone(),
+            function.args.clone(),
+            function.return_type.clone(),
+            &code,
+        )
+    }
+}
+
+impl<I: 'static + Instruction + Into<bril::Instruction>> Function<I> {
+    pub fn to_bril(&self) -> bril::Function {
+        bril::Function {
+            name: self.name.clone(),
+            args: self.args.clone(),
+            return_type: self.return_type.clone(),
+            instrs: self.to_code().into_iter().map(Code::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::instruction::BrilInstruction;
+
+    fn function() -> bril::Function {
+        let program = r#"{"functions": [{
+            "name": "main",
+            "args": [{"name": "n", "type": "int"}],
+            "type": "int",
+            "instrs": [
+                {"dest": "i", "op": "const", "type": "int", "value": 0},
+                {"label": "loop"},
+                {"dest": "c", "op": "lt", "type": "bool", "args": ["i", "n"]},
+                {"op": "br", "args": ["c"], "labels": ["body", "done"]},
+                {"label": "body"},
+                {"dest": "i", "op": "add", "type": "int", "args": ["i", "i"]},
+                {"op": "jmp", "labels": ["loop"]},
+                {"label": "done"},
+                {"op": "ret", "args": ["i"]}
+            ]
+        }]}"#;
+        let program: bril::Program = serde_json::from_str(program).unwrap();
+        program.functions[0].clone()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let original = function();
+        let function = Function::<BrilInstruction>::from_bril(&original).unwrap();
+        assert_eq!(function.name(), "main");
+        assert_eq!(function.args().len(), 1);
+        assert_eq!(function.return_type(), Some(&bril::Type::Int));
+
+        let converted = function.to_bril();
+        assert_eq!(converted.instrs, original.instrs);
+        assert_eq!(
+            serde_json::to_value(&converted).unwrap(),
+            serde_json::to_value(&original).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_update_blocks() {
+        let mut function = Function::<BrilInstruction>::from_bril(&function()).unwrap();
+        let split = function.update_blocks(|blocks| {
+            let split = blocks.split_edge("loop", "done")?;
+            Ok::<_, CompilerError>(split.label().clone())
+        });
+        let split = split.unwrap();
+
+        // The function can move, the blocks still point into its context
+        let function = Box::new(function);
+        let cfg = function.cfg().unwrap();
+        let block = function.blocks().get(&split).unwrap();
+        assert_eq!(cfg.predecessors(block).len(), 1);
+        assert_eq!(cfg.exit(), function.blocks().exit().unwrap());
+        assert!(function
+            .to_code()
+            .iter()
+            .any(|code| matches!(code, Code::Label { label } if *label == split)));
+    }
+}
@@ -15,6 +15,7 @@ enum Traversal {
 
 pub struct GraphIter<G: Graph> {
     nodes: Vec<G::Node>,
+    back_edges: Vec<(G::Node, G::Node)>,
     iter: std::ops::Range<usize>,
 }
 
@@ -22,33 +23,58 @@ impl<G: Graph> Clone for GraphIter<G> {
     fn clone(&self) -> Self {
         GraphIter {
             nodes: self.nodes.clone(),
+            back_edges: self.back_edges.clone(),
             iter: self.iter.clone(),
         }
     }
 }
 
+/**
+ * Depth first search from `root` with an explicit stack, so that huge graphs
+ * don't overflow the call stack. Successors are visited in the order the graph
+ * lists them, the same as a recursive search would. An edge to a node that is
+ * still grey, i.e. on the stack, is a back edge.
+ */
 fn traverse<G: Graph>(
     graph: &G,
     nodes: &mut Vec<G::Node>,
+    back_edges: &mut Vec<(G::Node, G::Node)>,
     colors: &mut HashMap<G::Node, Color>,
     traversal: &Traversal,
-    node: G::Node,
+    root: G::Node,
 ) {
-    if colors.contains_key(&node) {
+    if colors.contains_key(&root) {
         return;
     }
-    colors.insert(node, Color::Grey);
-    match traversal {
-        Traversal::PreOrder => nodes.push(node),
-        Traversal::PostOrder => {}
-    }
-    for child in graph.successors(node) {
-        traverse(graph, nodes, colors, traversal, child);
-    }
-    *colors.get_mut(&node).unwrap() = Color::Black;
-    match traversal {
-        Traversal::PreOrder => {}
-        Traversal::PostOrder => nodes.push(node),
+    // Each entry is a grey node and the successors it has left to visit
+    let mut stack = vec![(root, graph.successors(root).collect::<Vec<_>>().into_iter())];
+    colors.insert(root, Color::Grey);
+    if let Traversal::PreOrder = traversal {
+        nodes.push(root);
+    }
+    while let Some((node, children)) = stack.last_mut() {
+        let node = *node;
+        match children.next() {
+            Some(child) => match colors.get(&child) {
+                None => {
+                    colors.insert(child, Color::Grey);
+                    if let Traversal::PreOrder = traversal {
+                        nodes.push(child);
+                    }
+                    let children: Vec<_> = graph.successors(child).collect();
+                    stack.push((child, children.into_iter()));
+                }
+                Some(Color::Grey) => back_edges.push((node, child)),
+                Some(Color::Black) => {}
+            },
+            None => {
+                stack.pop();
+                *colors.get_mut(&node).unwrap() = Color::Black;
+                if let Traversal::PostOrder = traversal {
+                    nodes.push(node);
+                }
+            }
+        }
     }
 }
 
@@ -56,11 +82,32 @@ impl<G: Graph> GraphIter<G> {
     fn new(graph: &G, root: Option<G::Node>, traversal: Traversal) -> Self {
         let mut colors = HashMap::new();
         let mut nodes = Vec::new();
+        let mut back_edges = Vec::new();
         if let Some(root) = root {
-            traverse(graph, &mut nodes, &mut colors, &traversal, root);
+            traverse(
+                graph,
+                &mut nodes,
+                &mut back_edges,
+                &mut colors,
+                &traversal,
+                root,
+            );
         }
         let iter = 0..nodes.len();
-        GraphIter { nodes, iter }
+        GraphIter {
+            nodes,
+            back_edges,
+            iter,
+        }
+    }
+
+    /**
+     * The edges the search found going back to a node it was still visiting,
+     * in the order they were found. Every cycle reachable from the root has at
+     * least one of them.
+     */
+    pub fn back_edges(&self) -> &[(G::Node, G::Node)] {
+        &self.back_edges
     }
 }
 
@@ -194,6 +241,20 @@ pub trait Graph {
         GraphIter::new(self, Some(node), Traversal::PostOrder)
     }
 
+    fn reverse_post_order_iter(&self) -> std::iter::Rev<GraphIter<Self>>
+    where
+        Self: Sized,
+    {
+        self.post_order_iter().rev()
+    }
+
+    fn reverse_post_order_iter_at(&self, node: Self::Node) -> std::iter::Rev<GraphIter<Self>>
+    where
+        Self: Sized,
+    {
+        self.post_order_iter_at(node).rev()
+    }
+
     fn pre_order_iter(&self) -> GraphIter<Self>
     where
         Self: Sized,
@@ -352,6 +413,8 @@ impl<G: MutableIndirectGraph> MutableIndirectGraph for Inverse<G> {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
     use std::collections::BTreeSet;
 
     #[derive(Clone, Debug)]
@@ -395,6 +458,15 @@ pub(crate) mod tests {
             self.pred.entry(to).or_insert_with(BTreeSet::new).insert(from);
         }
 
+        pub fn remove_edge(&mut self, from: i32, to: i32) {
+            if let Some(succ) = self.succ.get_mut(&from) {
+                succ.remove(&to);
+            }
+            if let Some(pred) = self.pred.get_mut(&to) {
+                pred.remove(&from);
+            }
+        }
+
         pub fn extend_edges(&mut self, edges: impl Iterator<Item = (i32, i32)>) {
             for (from, to) in edges {
                 self.add_edge(from, to);
@@ -511,4 +583,97 @@ pub(crate) mod tests {
         let mut inv = Inverse(&mut graph);
         assert_eq!(inv.get_mut(1), &mut TestNode(102));
     }
+
+    fn recursive_order(
+        graph: &SimpleGraph,
+        node: i32,
+        visited: &mut Vec<i32>,
+        pre: &mut Vec<i32>,
+        post: &mut Vec<i32>,
+    ) {
+        if visited.contains(&node) {
+            return;
+        }
+        visited.push(node);
+        pre.push(node);
+        for succ in graph.successors(node) {
+            recursive_order(graph, succ, visited, pre, post);
+        }
+        post.push(node);
+    }
+
+    #[test]
+    fn test_traversal_order() {
+        // 0 -> (1 | 3), 1 -> 2, 2 -> (0 | 3), 3 -> 4
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..5);
+        graph.extend_edges(vec![(0, 1), (0, 3), (1, 2), (2, 0), (2, 3), (3, 4)].into_iter());
+        assert_eq!(
+            graph.pre_order_iter().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(
+            graph.post_order_iter().collect::<Vec<_>>(),
+            vec![4, 3, 2, 1, 0]
+        );
+        assert_eq!(
+            graph.reverse_post_order_iter().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(
+            graph.pre_order_iter_at(2).collect::<Vec<_>>(),
+            vec![2, 0, 1, 3, 4]
+        );
+        assert_eq!(graph.pre_order_iter().back_edges(), &[(2, 0)]);
+        assert!(graph.pre_order_iter_at(3).back_edges().is_empty());
+    }
+
+    #[test]
+    fn test_random_traversals() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let mut graph = SimpleGraph::new();
+            graph.set_entry(0);
+            graph.extend_nodes(0..10);
+            let num_edges = Uniform::new(0, 25).sample(&mut rng);
+            for _ in 0..num_edges {
+                let src = Uniform::new(0, 10).sample(&mut rng);
+                let dst = Uniform::new(0, 10).sample(&mut rng);
+                graph.add_edge(src, dst);
+            }
+            let (mut pre, mut post) = (Vec::new(), Vec::new());
+            recursive_order(&graph, 0, &mut Vec::new(), &mut pre, &mut post);
+            assert_eq!(graph.pre_order_iter().collect::<Vec<_>>(), pre);
+            assert_eq!(graph.post_order_iter().collect::<Vec<_>>(), post);
+            post.reverse();
+            assert_eq!(graph.reverse_post_order_iter().collect::<Vec<_>>(), post);
+
+            // Removing the back edges leaves the reachable part acyclic, so
+            // every remaining edge goes forward in reverse post-order
+            let back_edges = graph.post_order_iter().back_edges().to_vec();
+            let position: HashMap<_, _> = post.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+            for &node in post.iter() {
+                for succ in graph.successors(node) {
+                    let is_back = back_edges.contains(&(node, succ));
+                    assert_eq!(is_back, position[&succ] <= position[&node]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // Deep enough to overflow the stack with one call per node
+        let len = 100_000;
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..len);
+        graph.extend_edges((1..len).map(|n| (n - 1, n)));
+        graph.add_edge(len - 1, 0);
+        let pre = graph.pre_order_iter();
+        assert_eq!(pre.back_edges(), &[(len - 1, 0)]);
+        assert!(pre.eq(0..len));
+        assert!(graph.post_order_iter().eq((0..len).rev()));
+    }
 }
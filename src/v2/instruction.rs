@@ -274,6 +274,50 @@ where
         }
     }
 
+    fn is_jump(&self) -> bool {
+        matches!(
+            self.base(),
+            InstructionBase::Effect {
+                op: EffectOps::Jump,
+                ..
+            }
+        )
+    }
+
+    fn is_phi(&self) -> bool {
+        matches!(
+            self.base(),
+            InstructionBase::Value {
+                op: ValueOps::Phi,
+                ..
+            }
+        )
+    }
+
+    /**
+     * Drops the arguments of a phi that come from the block `label`.
+     */
+    fn remove_phi_label(mut self, label: &str) -> Self {
+        if let InstructionBase::Value {
+            op: ValueOps::Phi,
+            args,
+            labels,
+            ..
+        } = self.mut_base()
+        {
+            let mut idx = 0;
+            while idx < labels.len() {
+                if labels[idx] == label {
+                    labels.remove(idx);
+                    args.remove(idx);
+                } else {
+                    idx += 1;
+                }
+            }
+        }
+        self.into_base().into()
+    }
+
     fn rename_label(mut self, old: &str, new: &str) -> Self {
         match self.mut_base() {
             InstructionBase::Effect { labels, .. } => {
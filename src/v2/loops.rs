@@ -0,0 +1,399 @@
+use super::dominance_tree::DominanceTree;
+use super::graph::Graph;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct NaturalLoop<N> {
+    header: N,
+    latches: Vec<N>,
+    /** In the order they were found, so that iteration is deterministic */
+    nodes: Vec<N>,
+    members: HashSet<N>,
+}
+
+impl<N: Copy + Eq + std::hash::Hash> NaturalLoop<N> {
+    pub fn header(&self) -> N {
+        self.header
+    }
+
+    /**
+     * The sources of the back edges into the header.
+     */
+    pub fn latches(&self) -> &[N] {
+        &self.latches
+    }
+
+    /**
+     * All nodes in the loop, starting with the header and then in the order
+     * they were found walking backwards from the latches.
+     */
+    pub fn nodes(&self) -> impl Iterator<Item = N> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    pub fn contains(&self, node: N) -> bool {
+        self.members.contains(&node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /**
+     * The only latch, if the loop has a single back edge.
+     */
+    pub fn latch(&self) -> Option<N> {
+        match self.latches.as_slice() {
+            [latch] => Some(*latch),
+            _ => None,
+        }
+    }
+
+    /**
+     * The predecessors of the header from outside the loop, in the order the
+     * graph lists them.
+     */
+    pub fn entering_nodes<'g, G: Graph<Node = N>>(
+        &'g self,
+        graph: &'g G,
+    ) -> impl Iterator<Item = N> + 'g {
+        graph
+            .predecessors(self.header)
+            .filter(move |&pred| !self.contains(pred))
+    }
+
+    /**
+     * The block that control always passes through right before entering the
+     * loop: the header's only predecessor from outside, with the header as its
+     * only successor.
+     */
+    pub fn preheader<G: Graph<Node = N>>(&self, graph: &G) -> Option<N> {
+        let mut entering = self.entering_nodes(graph);
+        match (entering.next(), entering.next()) {
+            (Some(pred), None) if graph.successors(pred).count() == 1 => Some(pred),
+            _ => None,
+        }
+    }
+
+    /**
+     * Nodes in the loop with a successor outside of it, in pre-order.
+     */
+    pub fn exiting_nodes<G: Graph<Node = N>>(&self, graph: &G) -> Vec<N> {
+        graph
+            .pre_order_iter_at(self.header)
+            .filter(|&node| {
+                self.contains(node) && graph.successors(node).any(|succ| !self.contains(succ))
+            })
+            .collect()
+    }
+
+    /**
+     * Nodes outside the loop that are the target of an edge leaving it, in
+     * pre-order.
+     */
+    pub fn exits<G: Graph<Node = N>>(&self, graph: &G) -> Vec<N> {
+        let mut exits = Vec::new();
+        for node in self.exiting_nodes(graph) {
+            for succ in graph.successors(node) {
+                if !self.contains(succ) && !exits.contains(&succ) {
+                    exits.push(succ);
+                }
+            }
+        }
+        exits
+    }
+}
+
+/**
+ * The edges whose target dominates their source, in the pre-order of their
+ * sources.
+ */
+pub fn back_edges<G: Graph>(graph: &G, dom: &DominanceTree<G>) -> Vec<(G::Node, G::Node)> {
+    graph
+        .pre_order_iter()
+        .flat_map(|node| {
+            graph
+                .successors(node)
+                .filter(move |&succ| dom.dominates(succ, node))
+                .map(move |succ| (node, succ))
+        })
+        .collect()
+}
+
+/**
+ * Finds the natural loop of every back edge. Loops that share a header are
+ * merged into one. Loops are returned in the pre-order of their headers.
+ */
+pub fn natural_loops<G: Graph>(
+    graph: &G,
+    dom: &DominanceTree<G>,
+) -> Vec<NaturalLoop<G::Node>> {
+    let mut loops: Vec<NaturalLoop<G::Node>> = Vec::new();
+    let mut header_idx = HashMap::new();
+    let pre_order: HashMap<_, _> = graph
+        .pre_order_iter()
+        .enumerate()
+        .map(|(idx, node)| (node, idx))
+        .collect();
+    for (latch, header) in back_edges(graph, dom) {
+        let idx = *header_idx.entry(header).or_insert_with(|| {
+            loops.push(NaturalLoop {
+                header,
+                latches: Vec::new(),
+                nodes: vec![header],
+                members: HashSet::from([header]),
+            });
+            loops.len() - 1
+        });
+        let natural_loop = &mut loops[idx];
+        natural_loop.latches.push(latch);
+        // Everything that reaches the latch without going through the header.
+        // Unreachable predecessors aren't dominated by the header, so skip them.
+        let mut stack = vec![latch];
+        while let Some(node) = stack.pop() {
+            if dom.dominates(header, node) && natural_loop.members.insert(node) {
+                natural_loop.nodes.push(node);
+                stack.extend(graph.predecessors(node));
+            }
+        }
+    }
+    loops.sort_by_key(|natural_loop| pre_order[&natural_loop.header]);
+    loops
+}
+
+/**
+ * The natural loops of a graph arranged by nesting. Once loops sharing a
+ * header are merged, any two loops are either disjoint or one contains the
+ * other, so every loop has at most one parent: the smallest loop containing
+ * it. Loops are identified by their index in `loops()`.
+ */
+#[derive(Debug, Clone)]
+pub struct LoopForest<N> {
+    loops: Vec<NaturalLoop<N>>,
+    parent: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    depth: Vec<usize>,
+    /** The innermost loop containing each node in a loop */
+    innermost: HashMap<N, usize>,
+}
+
+impl<N: Copy + Eq + std::hash::Hash> LoopForest<N> {
+    pub fn new<G: Graph<Node = N>>(graph: &G, dom: &DominanceTree<G>) -> Self {
+        let loops = natural_loops(graph, dom);
+        let mut parent = vec![None; loops.len()];
+        let mut children = vec![Vec::new(); loops.len()];
+        let mut depth = vec![1; loops.len()];
+        // Headers are in pre-order, so an enclosing loop always comes first
+        for idx in 0..loops.len() {
+            let header = loops[idx].header;
+            parent[idx] = (0..idx)
+                .filter(|&outer| loops[outer].contains(header))
+                .min_by_key(|&outer| loops[outer].len());
+            if let Some(outer) = parent[idx] {
+                children[outer].push(idx);
+                depth[idx] = depth[outer] + 1;
+            }
+        }
+        let mut innermost = HashMap::new();
+        for (idx, natural_loop) in loops.iter().enumerate() {
+            for node in natural_loop.nodes() {
+                let entry = innermost.entry(node).or_insert(idx);
+                if depth[*entry] < depth[idx] {
+                    *entry = idx;
+                }
+            }
+        }
+        LoopForest {
+            loops,
+            parent,
+            children,
+            depth,
+            innermost,
+        }
+    }
+
+    /**
+     * All loops, in the pre-order of their headers.
+     */
+    pub fn loops(&self) -> &[NaturalLoop<N>] {
+        &self.loops
+    }
+
+    pub fn get(&self, idx: usize) -> &NaturalLoop<N> {
+        &self.loops[idx]
+    }
+
+    /**
+     * The outermost loops.
+     */
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.loops.len()).filter(move |&idx| self.parent[idx].is_none())
+    }
+
+    pub fn parent(&self, idx: usize) -> Option<usize> {
+        self.parent[idx]
+    }
+
+    pub fn children(&self, idx: usize) -> &[usize] {
+        &self.children[idx]
+    }
+
+    /**
+     * The nesting depth of a loop, starting at 1 for outermost loops.
+     */
+    pub fn depth(&self, idx: usize) -> usize {
+        self.depth[idx]
+    }
+
+    pub fn innermost_loop(&self, node: N) -> Option<usize> {
+        self.innermost.get(&node).copied()
+    }
+
+    /**
+     * The number of loops containing the node, 0 if it isn't in any loop.
+     */
+    pub fn loop_depth(&self, node: N) -> usize {
+        self.innermost_loop(node).map_or(0, |idx| self.depth[idx])
+    }
+
+    /**
+     * Every loop before the loops enclosing it, so that work done on inner
+     * loops can be picked up by outer ones.
+     */
+    pub fn inner_to_outer(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut stack: Vec<(usize, bool)> = self.roots().map(|idx| (idx, false)).collect();
+        stack.reverse();
+        while let Some((idx, visited)) = stack.pop() {
+            if visited {
+                order.push(idx);
+                continue;
+            }
+            stack.push((idx, true));
+            stack.extend(self.children[idx].iter().rev().map(|&child| (child, false)));
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::graph::tests::SimpleGraph;
+
+    #[test]
+    fn test_nested() {
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..6);
+        graph.extend_edges(
+            vec![(0, 1), (1, 2), (2, 3), (3, 2), (3, 4), (4, 1), (1, 5)].into_iter(),
+        );
+        let dom = DominanceTree::new(&graph);
+        let loops = natural_loops(&graph, &dom);
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header(), 1);
+        assert_eq!(loops[0].latches(), &[4]);
+        assert_eq!(loops[0].nodes().collect::<HashSet<_>>(), HashSet::from([1, 2, 3, 4]));
+        assert_eq!(loops[1].header(), 2);
+        assert_eq!(loops[1].latches(), &[3]);
+        assert_eq!(loops[1].nodes().collect::<HashSet<_>>(), HashSet::from([2, 3]));
+    }
+
+    #[test]
+    fn test_shared_header() {
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..5);
+        graph.extend_edges(vec![(0, 1), (1, 2), (1, 3), (2, 1), (3, 1), (1, 4)].into_iter());
+        let dom = DominanceTree::new(&graph);
+        let loops = natural_loops(&graph, &dom);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].header(), 1);
+        assert_eq!(loops[0].latches(), &[2, 3]);
+        assert_eq!(loops[0].nodes().collect::<HashSet<_>>(), HashSet::from([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_loop_shape() {
+        // 0 -> 1 -> 2 -> (1 | 3), 1 -> 4
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..5);
+        graph.extend_edges(vec![(0, 1), (1, 2), (2, 1), (2, 3), (1, 4)].into_iter());
+        let dom = DominanceTree::new(&graph);
+        assert_eq!(back_edges(&graph, &dom), vec![(2, 1)]);
+        let loops = natural_loops(&graph, &dom);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].latch(), Some(2));
+        assert_eq!(loops[0].preheader(&graph), Some(0));
+        assert_eq!(loops[0].entering_nodes(&graph).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(loops[0].exiting_nodes(&graph), vec![1, 2]);
+        let exits: HashSet<_> = loops[0].exits(&graph).into_iter().collect();
+        assert_eq!(exits, HashSet::from([3, 4]));
+
+        // A second way in means there is no preheader
+        graph.add_node(5);
+        graph.extend_edges(vec![(0, 5), (5, 1)].into_iter());
+        let dom = DominanceTree::new(&graph);
+        let loops = natural_loops(&graph, &dom);
+        assert_eq!(loops[0].preheader(&graph), None);
+    }
+
+    #[test]
+    fn test_forest() {
+        // Two sibling loops (2, 3) and (4, 5) nested in 1, and a separate loop 7
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..9);
+        graph.extend_edges(
+            vec![
+                (0, 1),
+                (1, 2),
+                (2, 3),
+                (3, 2),
+                (3, 4),
+                (4, 5),
+                (5, 4),
+                (5, 6),
+                (6, 1),
+                (6, 7),
+                (7, 7),
+                (7, 8),
+            ]
+            .into_iter(),
+        );
+        let dom = DominanceTree::new(&graph);
+        let forest = LoopForest::new(&graph, &dom);
+        let headers: Vec<_> = forest.loops().iter().map(|l| l.header()).collect();
+        assert_eq!(headers, vec![1, 2, 4, 7]);
+        assert_eq!(forest.roots().collect::<Vec<_>>(), vec![0, 3]);
+        assert_eq!(forest.children(0), &[1, 2]);
+        assert_eq!(forest.parent(1), Some(0));
+        assert_eq!(forest.parent(2), Some(0));
+        assert_eq!(forest.parent(3), None);
+        assert_eq!(forest.depth(0), 1);
+        assert_eq!(forest.depth(2), 2);
+        assert_eq!(forest.innermost_loop(3), Some(1));
+        assert_eq!(forest.innermost_loop(6), Some(0));
+        assert_eq!(forest.innermost_loop(8), None);
+        assert_eq!(forest.loop_depth(5), 2);
+        assert_eq!(forest.loop_depth(7), 1);
+        assert_eq!(forest.loop_depth(0), 0);
+        assert_eq!(forest.inner_to_outer(), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn test_unreachable_cycle() {
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..3);
+        graph.extend_edges(vec![(1, 2), (2, 1)].into_iter());
+        let dom = DominanceTree::new(&graph);
+        assert!(natural_loops(&graph, &dom).is_empty());
+    }
+}
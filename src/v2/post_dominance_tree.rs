@@ -0,0 +1,173 @@
+use super::dominance_tree::DominanceTree;
+use super::graph::{Graph, Inverse};
+
+/**
+ * The dominance tree of the inverse graph, rooted at the exit node. A node
+ * post-dominates another if every path from it to the exit goes through the
+ * first. Nodes that can't reach the exit (e.g. infinite loops) aren't in the
+ * tree, and neither post-dominate nor are post-dominated.
+ */
+pub struct PostDominanceTree<'a, G: Graph> {
+    tree: DominanceTree<'a, G>,
+}
+
+impl<'a, G: Graph> PostDominanceTree<'a, G> {
+    pub fn new(graph: &'a G) -> Self {
+        PostDominanceTree {
+            tree: DominanceTree::build(&Inverse(graph)),
+        }
+    }
+
+    pub fn post_dominates(&self, post_dominator: G::Node, post_dominated: G::Node) -> bool {
+        self.tree.dominates(post_dominator, post_dominated)
+    }
+
+    pub fn strictly_post_dominates(
+        &self,
+        post_dominator: G::Node,
+        post_dominated: G::Node,
+    ) -> bool {
+        self.tree.strictly_dominates(post_dominator, post_dominated)
+    }
+
+    pub fn immediate_post_dominator(&self, node: G::Node) -> Option<G::Node> {
+        self.tree.immediate_dominator(node)
+    }
+
+    pub fn immediately_post_dominated_nodes(
+        &self,
+        node: G::Node,
+    ) -> impl Iterator<Item = G::Node> + '_ {
+        self.tree.immediately_dominated_nodes(node)
+    }
+
+    pub fn post_dominated_nodes(&self, node: G::Node) -> impl Iterator<Item = G::Node> + '_ {
+        self.tree.dominated_nodes(node)
+    }
+
+    pub fn strictly_post_dominated_nodes(
+        &self,
+        node: G::Node,
+    ) -> impl Iterator<Item = G::Node> + '_ {
+        self.tree.strictly_dominated_nodes(node)
+    }
+
+    /**
+     * The nodes with a successor post-dominated by `node`, without being
+     * strictly post-dominated by it themselves. These are exactly the
+     * branches that `node` is control dependent on.
+     */
+    pub fn post_dominance_frontier(&self, node: G::Node) -> impl Iterator<Item = G::Node> + '_ {
+        self.tree.dominance_frontier(node)
+    }
+}
+
+impl<'a, G: Graph> Graph for PostDominanceTree<'a, G> {
+    type Node = G::Node;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        self.tree.entry_node()
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        self.tree.exit_node()
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        self.tree.nodes()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.tree.predecessors(node)
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.tree.successors(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::graph::tests::SimpleGraph;
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_basic() {
+        // 1 -> 2 -> (3 | 4) -> 5 -> (2 | 6)
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(1);
+        graph.set_exit(6);
+        graph.extend_nodes(1..7);
+        graph.extend_edges(vec![(1, 2), (2, 3), (2, 4), (3, 5), (4, 5), (5, 2), (5, 6)].into_iter());
+        let post_dom = PostDominanceTree::new(&graph);
+
+        assert_eq!(post_dom.immediate_post_dominator(6), None);
+        assert_eq!(post_dom.immediate_post_dominator(5), Some(6));
+        assert_eq!(post_dom.immediate_post_dominator(4), Some(5));
+        assert_eq!(post_dom.immediate_post_dominator(3), Some(5));
+        assert_eq!(post_dom.immediate_post_dominator(2), Some(5));
+        assert_eq!(post_dom.immediate_post_dominator(1), Some(2));
+        assert!(post_dom.post_dominates(5, 1));
+        assert!(!post_dom.post_dominates(3, 2));
+        assert!(!post_dom.strictly_post_dominates(5, 5));
+
+        let frontier = |node| post_dom.post_dominance_frontier(node).collect::<HashSet<_>>();
+        assert_eq!(frontier(3), HashSet::from([2]));
+        assert_eq!(frontier(4), HashSet::from([2]));
+        assert_eq!(frontier(2), HashSet::from([5]));
+        assert_eq!(frontier(5), HashSet::from([5]));
+        assert!(frontier(1).is_empty());
+        assert!(frontier(6).is_empty());
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        // 2 loops forever, so it is left out of the tree
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.set_exit(3);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (0, 2), (2, 2), (1, 3)].into_iter());
+        let post_dom = PostDominanceTree::new(&graph);
+
+        assert_eq!(post_dom.immediate_post_dominator(1), Some(3));
+        assert_eq!(post_dom.immediate_post_dominator(0), Some(1));
+        assert_eq!(post_dom.immediate_post_dominator(2), None);
+        assert!(!post_dom.post_dominates(3, 2));
+        assert_eq!(post_dom.post_dominated_nodes(2).count(), 0);
+    }
+
+    #[test]
+    fn test_random_graphs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut graph = SimpleGraph::new();
+            graph.set_entry(0);
+            graph.set_exit(9);
+            graph.extend_nodes(0..10);
+
+            let num_edges = Uniform::new(0, 100).sample(&mut rng);
+            for _ in 0..num_edges {
+                let src = Uniform::new(0, 9).sample(&mut rng);
+                let dst = Uniform::new(1, 10).sample(&mut rng);
+                graph.add_edge(src, dst);
+            }
+
+            // Post-dominance is dominance on the inverse graph
+            let inverse = Inverse(&graph);
+            let dom = DominanceTree::new(&inverse);
+            let post_dom = PostDominanceTree::new(&graph);
+            for a in graph.nodes() {
+                assert_eq!(post_dom.immediate_post_dominator(a), dom.immediate_dominator(a));
+                for b in graph.nodes() {
+                    assert_eq!(post_dom.post_dominates(a, b), dom.dominates(a, b));
+                }
+                let frontier = post_dom.post_dominance_frontier(a).collect::<HashSet<_>>();
+                assert_eq!(frontier, dom.dominance_frontier(a).collect::<HashSet<_>>());
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use super::graph::Graph;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/**
+ * What is left of a graph after applying the T1 (remove a self-loop) and T2
+ * (merge a node into its only predecessor) transformations until neither
+ * applies. Each node of the limit graph is the header of a group of original
+ * nodes: the group's only node entered from outside it. A graph is reducible
+ * iff its limit graph is a single node. Unreachable nodes are ignored.
+ */
+pub struct LimitGraph<N> {
+    entry: Option<N>,
+    /** Pre-order number of every reachable original node */
+    pre_order: HashMap<N, usize>,
+    headers: Vec<N>,
+    members: HashMap<N, Vec<N>>,
+    pred: HashMap<N, Vec<N>>,
+    succ: HashMap<N, Vec<N>>,
+}
+
+impl<N: Copy + Eq + Hash + Debug> LimitGraph<N> {
+    pub fn new<G: Graph<Node = N>>(graph: &G) -> Self {
+        let nodes: Vec<_> = graph.pre_order_iter().collect();
+        let idx: HashMap<_, _> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let mut pred: Vec<BTreeSet<usize>> = nodes
+            .iter()
+            .map(|node| {
+                graph
+                    .predecessors(*node)
+                    .filter_map(|p| idx.get(&p).copied())
+                    .collect()
+            })
+            .collect();
+        let mut succ: Vec<BTreeSet<usize>> = nodes
+            .iter()
+            .map(|node| graph.successors(*node).map(|s| idx[&s]).collect())
+            .collect();
+        let mut members: Vec<Vec<usize>> = (0..nodes.len()).map(|i| vec![i]).collect();
+        let mut alive = vec![true; nodes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for n in 0..nodes.len() {
+                if !alive[n] {
+                    continue;
+                }
+                // T1
+                pred[n].remove(&n);
+                succ[n].remove(&n);
+                // T2, the entry has an implicit predecessor so is never merged
+                if n == 0 || pred[n].len() != 1 {
+                    continue;
+                }
+                let m = *pred[n].iter().next().unwrap();
+                let merged = std::mem::take(&mut members[n]);
+                members[m].extend(merged);
+                succ[m].remove(&n);
+                for s in std::mem::take(&mut succ[n]) {
+                    pred[s].remove(&n);
+                    if s != m {
+                        pred[s].insert(m);
+                        succ[m].insert(s);
+                    }
+                }
+                pred[n].clear();
+                alive[n] = false;
+                changed = true;
+            }
+        }
+
+        let headers: Vec<_> = (0..nodes.len()).filter(|n| alive[*n]).collect();
+        let to_nodes = |set: &BTreeSet<usize>| set.iter().map(|i| nodes[*i]).collect();
+        LimitGraph {
+            entry: nodes.first().copied(),
+            pre_order: idx,
+            headers: headers.iter().map(|n| nodes[*n]).collect(),
+            members: headers
+                .iter()
+                .map(|n| {
+                    let mut group = members[*n].clone();
+                    group.sort();
+                    (nodes[*n], group.into_iter().map(|i| nodes[i]).collect())
+                })
+                .collect(),
+            pred: headers
+                .iter()
+                .map(|n| (nodes[*n], to_nodes(&pred[*n])))
+                .collect(),
+            succ: headers
+                .iter()
+                .map(|n| (nodes[*n], to_nodes(&succ[*n])))
+                .collect(),
+        }
+    }
+
+    pub fn is_reducible(&self) -> bool {
+        self.headers.len() <= 1
+    }
+
+    /**
+     * The original nodes collapsed into `header`, in pre-order.
+     */
+    pub fn members(&self, header: N) -> &[N] {
+        &self.members[&header]
+    }
+
+    /**
+     * The strongly connected parts of the limit graph, each of which is a
+     * cycle with more than one entry. Regions are ordered by their first
+     * node in pre-order.
+     */
+    pub fn irreducible_regions(&self) -> Vec<IrreducibleRegion<N>> {
+        let reachable: HashMap<N, Vec<N>> = self
+            .headers
+            .iter()
+            .map(|header| (*header, self.pre_order_iter_at(*header).collect()))
+            .collect();
+        let mut regions: Vec<IrreducibleRegion<N>> = Vec::new();
+        for header in self.headers.iter() {
+            if regions.iter().any(|region| region.headers.contains(header)) {
+                continue;
+            }
+            let headers: Vec<_> = self
+                .headers
+                .iter()
+                .filter(|other| {
+                    reachable[header].contains(other) && reachable[other].contains(header)
+                })
+                .copied()
+                .collect();
+            if headers.len() < 2 {
+                continue;
+            }
+            let entries = headers
+                .iter()
+                .filter(|header| {
+                    Some(**header) == self.entry
+                        || self.pred[header].iter().any(|pred| !headers.contains(pred))
+                })
+                .copied()
+                .collect();
+            let mut nodes: Vec<_> = self
+                .headers
+                .iter()
+                .filter(|header| headers.contains(header))
+                .flat_map(|header| self.members[header].iter().copied())
+                .collect();
+            nodes.sort_by_key(|node| self.pre_order[node]);
+            regions.push(IrreducibleRegion {
+                headers,
+                entries,
+                nodes,
+            });
+        }
+        regions
+    }
+}
+
+impl<N: Copy + Eq + Hash + Debug> Graph for LimitGraph<N> {
+    type Node = N;
+
+    fn entry_node(&self) -> Option<Self::Node> {
+        self.entry
+    }
+
+    fn exit_node(&self) -> Option<Self::Node> {
+        None
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = Self::Node> + '_ {
+        self.headers.iter().copied()
+    }
+
+    fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.pred[&node].iter().copied()
+    }
+
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> + '_ {
+        self.succ[&node].iter().copied()
+    }
+}
+
+/**
+ * A cycle that can be entered at more than one node, so no single node
+ * dominates the rest of it.
+ */
+#[derive(Debug, Clone)]
+pub struct IrreducibleRegion<N> {
+    headers: Vec<N>,
+    entries: Vec<N>,
+    nodes: Vec<N>,
+}
+
+impl<N: Copy> IrreducibleRegion<N> {
+    /**
+     * The nodes of the limit graph making up the region.
+     */
+    pub fn headers(&self) -> &[N] {
+        &self.headers
+    }
+
+    /**
+     * The nodes where control can enter the region.
+     */
+    pub fn entries(&self) -> &[N] {
+        &self.entries
+    }
+
+    /**
+     * All original nodes collapsed into the region's headers, in pre-order.
+     * This may include nodes that only lead out of the region.
+     */
+    pub fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+}
+
+pub fn is_reducible<G: Graph>(graph: &G) -> bool {
+    LimitGraph::new(graph).is_reducible()
+}
+
+pub fn irreducible_regions<G: Graph>(graph: &G) -> Vec<IrreducibleRegion<G::Node>> {
+    LimitGraph::new(graph).irreducible_regions()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::dominance_tree::DominanceTree;
+    use crate::v2::graph::tests::SimpleGraph;
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+    use std::collections::HashSet;
+
+    /**
+     * A graph is reducible iff removing its back edges (edges to a dominator)
+     * leaves it acyclic.
+     */
+    fn is_reducible_by_dominance<G: Graph>(graph: &G) -> bool {
+        let dom = DominanceTree::new(graph);
+        let mut visited = HashSet::new();
+        let mut on_stack = HashSet::new();
+        fn has_cycle<G: Graph>(
+            graph: &G,
+            dom: &DominanceTree<G>,
+            node: G::Node,
+            visited: &mut HashSet<G::Node>,
+            on_stack: &mut HashSet<G::Node>,
+        ) -> bool {
+            visited.insert(node);
+            on_stack.insert(node);
+            for succ in graph.successors(node) {
+                if dom.dominates(succ, node) {
+                    continue;
+                }
+                if on_stack.contains(&succ)
+                    || (!visited.contains(&succ) && has_cycle(graph, dom, succ, visited, on_stack))
+                {
+                    return true;
+                }
+            }
+            on_stack.remove(&node);
+            false
+        }
+        match graph.entry_node() {
+            Some(entry) => !has_cycle(graph, &dom, entry, &mut visited, &mut on_stack),
+            None => true,
+        }
+    }
+
+    #[test]
+    fn test_reducible() {
+        // Nested loops with an early exit
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..6);
+        graph.extend_edges(
+            vec![
+                (0, 1),
+                (1, 2),
+                (2, 3),
+                (3, 2),
+                (3, 4),
+                (4, 1),
+                (2, 5),
+                (1, 5),
+            ]
+            .into_iter(),
+        );
+        assert!(is_reducible(&graph));
+        assert!(irreducible_regions(&graph).is_empty());
+        let limit = LimitGraph::new(&graph);
+        assert_eq!(limit.nodes().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(limit.members(0).len(), 6);
+    }
+
+    #[test]
+    fn test_irreducible() {
+        // 0 -> (1 | 2), 1 <-> 2, 2 -> 3
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..4);
+        graph.extend_edges(vec![(0, 1), (0, 2), (1, 2), (2, 1), (2, 3)].into_iter());
+        assert!(!is_reducible(&graph));
+        let regions = irreducible_regions(&graph);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].entries(), &[1, 2]);
+        assert_eq!(regions[0].nodes(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_irreducible_groups() {
+        // The cycle 1 -> 2 -> 3 -> 4 -> 1 is entered at 1 and 3, and 2 and 4
+        // are merged into them
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..6);
+        graph
+            .extend_edges(vec![(0, 1), (0, 3), (1, 2), (2, 3), (3, 4), (4, 1), (4, 5)].into_iter());
+        let limit = LimitGraph::new(&graph);
+        let mut headers: Vec<_> = limit.nodes().collect();
+        headers.sort();
+        assert_eq!(headers, vec![0, 1, 3]);
+        assert_eq!(limit.members(1), &[1, 2]);
+        let regions = limit.irreducible_regions();
+        assert_eq!(regions.len(), 1);
+        let mut entries = regions[0].entries().to_vec();
+        entries.sort();
+        assert_eq!(entries, vec![1, 3]);
+        let mut nodes = regions[0].nodes().to_vec();
+        nodes.sort();
+        assert_eq!(nodes, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut graph = SimpleGraph::new();
+        graph.set_entry(0);
+        graph.extend_nodes(0..3);
+        graph.extend_edges(vec![(1, 2), (2, 1)].into_iter());
+        assert!(is_reducible(&graph));
+    }
+
+    #[test]
+    fn test_random_graphs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let mut graph = SimpleGraph::new();
+            graph.set_entry(0);
+            graph.extend_nodes(0..10);
+            let num_edges = Uniform::new(0, 25).sample(&mut rng);
+            for _ in 0..num_edges {
+                let src = Uniform::new(0, 10).sample(&mut rng);
+                let dst = Uniform::new(1, 10).sample(&mut rng);
+                graph.add_edge(src, dst);
+            }
+            let limit = LimitGraph::new(&graph);
+            assert_eq!(limit.is_reducible(), is_reducible_by_dominance(&graph));
+            assert_eq!(limit.is_reducible(), limit.irreducible_regions().is_empty());
+            let reachable: HashSet<_> = graph.pre_order_iter().collect();
+            let members: Vec<_> = limit
+                .nodes()
+                .flat_map(|h| limit.members(h).to_vec())
+                .collect();
+            assert_eq!(members.len(), reachable.len());
+            assert_eq!(members.into_iter().collect::<HashSet<_>>(), reachable);
+        }
+    }
+}